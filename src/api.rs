@@ -1,12 +1,29 @@
-use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{get, web, App, Error, HttpResponse, HttpServer, Responder};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Server, ServiceRequest, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::{from_fn, Next};
 use serde::{Serialize, Deserialize};
 use log::{warn, error};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::pem::PemObject;
+use std::io;
 
+use crate::api_keys::{self, Scope};
 use crate::balance;
+use crate::config::ApiConfig;
+use crate::ledger::LedgerEntry;
+use crate::money::{self, Money};
+use crate::storage;
+use crate::tickets::{self, StatsPeriod};
 
 #[derive(Serialize, Deserialize)]
 pub struct BalanceResponse {
+    /// Whole coins, kept for clients written before `balance_minor`.
     pub balance: f64,
+    /// Exact balance in minor units; divide by `10^decimals` for whole coins.
+    pub balance_minor: Money,
+    pub decimals: u32,
 }
 
 #[derive(serde::Deserialize)]
@@ -14,63 +31,317 @@ struct ProfileQuery {
     user_id: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct TransactionsResponse {
+    pub user_id: String,
+    pub total: usize,
+    /// Amounts are in minor units, like `balance_minor`.
+    pub entries: Vec<LedgerEntry>,
+    pub decimals: u32,
+}
+
+#[derive(serde::Deserialize)]
+struct TransactionsQuery {
+    user_id: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+const MAX_TRANSACTIONS_LIMIT: usize = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct LeaderboardResponse {
+    pub total: usize,
+    pub offset: usize,
+    /// Balances are in minor units, like `balance_minor`.
+    pub entries: Vec<balance::LeaderboardEntry>,
+    pub decimals: u32,
+}
+
+#[derive(serde::Deserialize)]
+struct LeaderboardQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+const MAX_LEADERBOARD_LIMIT: usize = 100;
+
+#[derive(serde::Deserialize)]
+struct TicketStatsQuery {
+    period: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub message: String,
+}
+
+fn error_response(mut builder: actix_web::HttpResponseBuilder, error: &str, message: String) -> HttpResponse {
+    builder.json(ErrorResponse { error: error.to_string(), message })
+}
+
+/// 400 for a `user_id` that is not a Discord user id, before it reaches storage.
+fn reject_invalid_user_id(user_id: &str) -> Option<HttpResponse> {
+    if storage::is_valid_user_id(user_id) {
+        return None;
+    }
+    warn!("🛑 Rejected API request for invalid user id {:?}", user_id);
+    Some(error_response(HttpResponse::BadRequest(), "invalid_user_id", "`user_id` must be a numeric Discord user id.".to_string()))
+}
+
+/// Scope a request needs, by path. Unknown paths still need a valid key.
+fn required_scope(path: &str) -> Option<Scope> {
+    match path {
+        "/profile" | "/transactions" | "/leaderboard" => Some(Scope::ReadBalance),
+        _ if path.starts_with("/tickets") => Some(Scope::ReadTickets),
+        _ => None,
+    }
+}
+
+/// Reads the key from `Authorization: Bearer <key>` or `X-API-Key`.
+fn presented_key(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(value) = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()) {
+        return value.strip_prefix("Bearer ").map(|key| key.trim().to_string());
+    }
+    headers.get("X-API-Key").and_then(|value| value.to_str().ok()).map(|key| key.trim().to_string())
+}
+
+/// Rejects requests without an active API key (401) or without the scope the route needs (403).
+pub async fn require_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(key) = presented_key(&req).and_then(|secret| api_keys::authenticate(&secret)) else {
+        warn!("🛑 Rejected unauthenticated API request to {}", req.path());
+        let response = error_response(HttpResponse::Unauthorized(), "unauthorized", "A valid API key is required.".to_string());
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+    if let Some(scope) = required_scope(req.path())
+        && !key.scopes.contains(&scope)
+    {
+        warn!("🛑 API key {} lacks scope {} for {}", key.id, scope, req.path());
+        let response = error_response(HttpResponse::Forbidden(), "forbidden", format!("This API key lacks the `{}` scope.", scope));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
 #[get("/profile")]
 async fn profile(query: web::Query<ProfileQuery>) -> impl Responder {
     let user_id = &query.user_id;
+    if let Some(response) = reject_invalid_user_id(user_id) {
+        return response;
+    }
 
     if !balance::is_user_bound(user_id) {
         warn!("🛑 Attempting to access the profile of an unregistered user: {}", user_id);
-        return HttpResponse::BadRequest().body("🛑 Your account is not bound. please use /bind first.");
+        return error_response(HttpResponse::BadRequest(), "not_bound", "This account is not bound, please use /bind first.".to_string());
     }
 
-    let user_data = balance::load_user_data(user_id);
+    let user_data = match balance::load_user_data(user_id) {
+        Ok(user_data) => user_data,
+        Err(e) => {
+            error!("🛑 Failed to read profile of {}: {:?}", user_id, e);
+            return error_response(HttpResponse::InternalServerError(), "internal_error", "Failed to read profile.".to_string());
+        }
+    };
     let response = BalanceResponse {
-        balance: user_data.balance,
+        balance: user_data.balance.to_coins_f64(),
+        balance_minor: user_data.balance,
+        decimals: money::decimals(),
     };
     HttpResponse::Ok().json(response)
 }
 
+#[get("/transactions")]
+async fn transactions(query: web::Query<TransactionsQuery>) -> impl Responder {
+    let user_id = &query.user_id;
+    if let Some(response) = reject_invalid_user_id(user_id) {
+        return response;
+    }
+
+    if !balance::is_user_bound(user_id) {
+        warn!("🛑 Attempting to access the transactions of an unregistered user: {}", user_id);
+        return error_response(HttpResponse::BadRequest(), "not_bound", "This account is not bound, please use /bind first.".to_string());
+    }
+
+    let limit = query.limit.unwrap_or(balance::HISTORY_PAGE_SIZE).min(MAX_TRANSACTIONS_LIMIT);
+    match balance::transaction_history(user_id, query.offset.unwrap_or(0), limit) {
+        Ok((entries, total)) => HttpResponse::Ok().json(TransactionsResponse {
+            user_id: user_id.clone(),
+            total,
+            entries,
+            decimals: money::decimals(),
+        }),
+        Err(e) => {
+            error!("🛑 Failed to read transactions of {}: {:?}", user_id, e);
+            error_response(HttpResponse::InternalServerError(), "internal_error", "Failed to read transactions.".to_string())
+        }
+    }
+}
+
+#[get("/leaderboard")]
+async fn leaderboard(query: web::Query<LeaderboardQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(balance::LEADERBOARD_DEFAULT_SIZE).min(MAX_LEADERBOARD_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    match balance::leaderboard(limit, offset) {
+        Ok((entries, total)) => HttpResponse::Ok().json(LeaderboardResponse {
+            total,
+            offset,
+            entries,
+            decimals: money::decimals(),
+        }),
+        Err(e) => {
+            error!("🛑 Failed to read leaderboard: {:?}", e);
+            error_response(HttpResponse::InternalServerError(), "internal_error", "Failed to read leaderboard.".to_string())
+        }
+    }
+}
+
+#[get("/tickets/stats")]
+async fn ticket_stats(query: web::Query<TicketStatsQuery>) -> impl Responder {
+    let period = match query.period.as_deref() {
+        None => StatsPeriod::Week,
+        Some(period) => match StatsPeriod::parse(period) {
+            Some(period) => period,
+            None => return error_response(HttpResponse::BadRequest(), "invalid_period", "`period` must be one of day, week, month or all.".to_string()),
+        },
+    };
+    HttpResponse::Ok().json(tickets::stats(period))
+}
+
 pub fn init_api(cfg: &mut web::ServiceConfig) {
     cfg.service(profile);
+    cfg.service(transactions);
+    cfg.service(leaderboard);
+    cfg.service(ticket_stats);
 }
 
-pub async fn start_api_server() -> std::io::Result<()> {
-    HttpServer::new(|| App::new().configure(init_api))
-        .bind(("127.0.0.1", 8080))?
-        .run()
-        .await
+fn load_tls_config(cert_path: &str, key_path: &str) -> io::Result<rustls::ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", cert_path, err)))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", key_path, err)))?;
+    rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Binds the API as configured. The returned server is not running yet; the
+/// caller spawns it and stops it through `Server::handle` on shutdown, so
+/// actix's own signal handling is disabled.
+pub fn start_api_server(config: &ApiConfig) -> io::Result<Server> {
+    let mut server = HttpServer::new(|| App::new().wrap(from_fn(require_api_key)).configure(init_api))
+        .disable_signals();
+    if let Some(workers) = config.workers {
+        server = server.workers(workers);
+    }
+    let address = (config.bind_address.as_str(), config.port);
+    server = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            server.bind_rustls_0_23(address, load_tls_config(cert_path, key_path)?)?
+        }
+        (None, None) => server.bind(address)?,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "api.tls_cert_path and api.tls_key_path must be set together",
+            ));
+        }
+    };
+    let scheme = if config.tls_cert_path.is_some() { "https" } else { "http" };
+    println!("🌐 API listening on {}://{}:{}", scheme, config.bind_address, config.port);
+    Ok(server.run())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use actix_web::{test, App, http::StatusCode};
-    use serde_json;
+
+    /// A request carrying a fresh key with `scopes`.
+    fn request_with_scopes(uri: &str, scopes: Vec<Scope>) -> test::TestRequest {
+        // Every test shares one key store; only the first call loads it.
+        let _ = api_keys::init(&std::env::temp_dir().join(format!("arcwarden-api-keys-{}", std::process::id())));
+        let (_, secret) = api_keys::create_key("test", scopes, 0).expect("🛑 Error creating API key");
+        test::TestRequest::get()
+            .uri(uri)
+            .insert_header((AUTHORIZATION, format!("Bearer {}", secret)))
+    }
+
+    fn authorized(uri: &str) -> test::TestRequest {
+        request_with_scopes(uri, Scope::ALL.to_vec())
+    }
 
     #[actix_web::test]
-    async fn test_profile_unbound() {
-        let app = test::init_service(App::new().configure(init_api)).await;
+    async fn test_requires_api_key() {
+        let app = test::init_service(App::new().wrap(from_fn(require_api_key)).configure(init_api)).await;
+        let req = test::TestRequest::get().uri("/profile?user_id=test").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body = test::read_body(resp).await;
+        let response: ErrorResponse = serde_json::from_slice(&body)
+            .expect("🛑 Error deserializing response");
+        assert_eq!(response.error, "unauthorized");
+
         let req = test::TestRequest::get()
             .uri("/profile?user_id=test")
+            .insert_header(("X-API-Key", "aw_0000_invalid"))
             .to_request();
         let resp = test::call_service(&app, req).await;
-        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
     }
 
     #[actix_web::test]
-    async fn test_profile_bound() {
-        let user_id = "!testApiBackport";
+    async fn test_requires_scope() {
+        let app = test::init_service(App::new().wrap(from_fn(require_api_key)).configure(init_api)).await;
+        let req = request_with_scopes("/tickets/stats", vec![Scope::ReadBalance]).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body = test::read_body(resp).await;
+        let response: ErrorResponse = serde_json::from_slice(&body)
+            .expect("🛑 Error deserializing response");
+        assert_eq!(response.error, "forbidden");
+    }
 
-        let _ = crate::balance::bind_user(user_id);
+    #[actix_web::test]
+    async fn test_profile_unbound() {
+        crate::balance::init_test_storage();
+        let app = test::init_service(App::new().wrap(from_fn(require_api_key)).configure(init_api)).await;
+        let req = authorized("/profile?user_id=700000000000000100").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(resp).await;
+        let response: ErrorResponse = serde_json::from_slice(&body)
+            .expect("🛑 Error deserializing response");
+        assert_eq!(response.error, "not_bound");
+    }
 
-        let mut user_data = crate::balance::load_user_data(user_id);
-        user_data.balance = 42.0;
-        let _ = crate::balance::save_user_data(user_id, &user_data);
+    #[actix_web::test]
+    async fn test_rejects_path_traversal() {
+        crate::balance::init_test_storage();
+        let app = test::init_service(App::new().wrap(from_fn(require_api_key)).configure(init_api)).await;
+        for uri in ["/profile?user_id=..%2Fconfig", "/transactions?user_id=..%2F..%2Fetc%2Fpasswd", "/profile?user_id=test"] {
+            let resp = test::call_service(&app, authorized(uri).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let body = test::read_body(resp).await;
+            let response: ErrorResponse = serde_json::from_slice(&body)
+                .expect("🛑 Error deserializing response");
+            assert_eq!(response.error, "invalid_user_id");
+        }
+    }
 
-        let app = test::init_service(App::new().configure(init_api)).await;
-        let req = test::TestRequest::get()
-            .uri(&format!("/profile?user_id={}", user_id))
-            .to_request();
+    #[actix_web::test]
+    async fn test_profile_bound() {
+        let user_id = "700000000000000101";
+        crate::balance::fund_test_account(user_id, "42");
+
+        let app = test::init_service(App::new().wrap(from_fn(require_api_key)).configure(init_api)).await;
+        let req = authorized(&format!("/profile?user_id={}", user_id)).to_request();
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), StatusCode::OK);
 
@@ -78,5 +349,80 @@ mod tests {
         let response: BalanceResponse = serde_json::from_slice(&body)
             .expect("🛑 Error deserializing response");
         assert_eq!(response.balance, 42.0);
+        assert_eq!(response.balance_minor, Money::from_minor(4200));
+        assert_eq!(response.decimals, 2);
     }
-}
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn test_transactions_bound() {
+        let user_id = "700000000000000102";
+        crate::balance::fund_test_account(user_id, "0");
+
+        let app = test::init_service(App::new().wrap(from_fn(require_api_key)).configure(init_api)).await;
+        let req = authorized(&format!("/transactions?user_id={}&limit=1", user_id)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = test::read_body(resp).await;
+        let response: TransactionsResponse = serde_json::from_slice(&body)
+            .expect("🛑 Error deserializing response");
+        assert_eq!(response.user_id, user_id);
+        assert!(response.total >= 1);
+        assert_eq!(response.entries.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_leaderboard() {
+        crate::balance::fund_test_account("700000000000000103", "0");
+
+        let app = test::init_service(App::new().wrap(from_fn(require_api_key)).configure(init_api)).await;
+        let req = authorized("/leaderboard?limit=2&offset=0").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = test::read_body(resp).await;
+        let response: LeaderboardResponse = serde_json::from_slice(&body)
+            .expect("🛑 Error deserializing response");
+        assert!(response.total >= 1);
+        assert!(response.entries.len() <= 2);
+        assert!(response.entries.windows(2).all(|pair| pair[0].balance >= pair[1].balance));
+        assert_eq!(response.entries[0].rank, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_ticket_stats() {
+        crate::balance::ensure_data_directory().expect("🛑 Error creating data directory");
+
+        let app = test::init_service(App::new().wrap(from_fn(require_api_key)).configure(init_api)).await;
+        let req = authorized("/tickets/stats?period=all").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = test::read_body(resp).await;
+        let response: tickets::TicketStats = serde_json::from_slice(&body)
+            .expect("🛑 Error deserializing response");
+        assert_eq!(response.period, StatsPeriod::All);
+
+        let req = authorized("/tickets/stats?period=decade").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(resp).await;
+        let response: ErrorResponse = serde_json::from_slice(&body)
+            .expect("🛑 Error deserializing response");
+        assert_eq!(response.error, "invalid_period");
+    }
+
+    #[tokio::test]
+    async fn test_server_runs_on_tokio() {
+        let config = ApiConfig { port: 0, workers: Some(1), ..ApiConfig::default() };
+        let server = start_api_server(&config).expect("🛑 Error binding API server");
+        let handle = server.handle();
+        let task = tokio::spawn(server);
+        handle.stop(true).await;
+        task.await.expect("🛑 API task panicked").expect("🛑 API server failed");
+
+        let config = ApiConfig { port: 0, tls_cert_path: Some("cert.pem".to_string()), ..ApiConfig::default() };
+        let err = start_api_server(&config).err().expect("🛑 TLS without a key was accepted");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
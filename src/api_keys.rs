@@ -0,0 +1,209 @@
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use crate::balance::{current_unix_time, DATA_DIRECTORY};
+use crate::fairness::{generate_seed, hash_seed};
+use crate::storage;
+
+/// Kept in its own directory so it is never mistaken for a user file.
+const API_KEYS_DIRECTORY: &str = "api";
+const API_KEYS_FILE: &str = "keys.json";
+const KEY_PREFIX: &str = "aw";
+
+static API_KEYS: OnceLock<Mutex<ApiKeyStore>> = OnceLock::new();
+
+/// What an API key may do. Routes map to a scope in `api::required_scope`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    #[serde(rename = "read:balance")]
+    ReadBalance,
+    #[serde(rename = "read:tickets")]
+    ReadTickets,
+    #[serde(rename = "write:economy")]
+    WriteEconomy,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::ReadBalance, Scope::ReadTickets, Scope::WriteEconomy];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::ReadBalance => "read:balance",
+            Scope::ReadTickets => "read:tickets",
+            Scope::WriteEconomy => "write:economy",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s.trim())
+            .ok_or_else(|| format!("unknown scope `{}`", s.trim()))
+    }
+}
+
+/// A stored key. Only the SHA-256 hash of the secret is kept; the secret
+/// itself is shown once when the key is created.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKey {
+    /// Public part of the secret, used to find the key and to revoke it.
+    pub id: String,
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<Scope>,
+    pub created_by: u64,
+    pub created_at: u64,
+    pub revoked_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ApiKeyStore {
+    keys: Vec<ApiKey>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl ApiKeyStore {
+    fn load(directory: &Path) -> io::Result<Self> {
+        let path = directory.join(API_KEYS_DIRECTORY).join(API_KEYS_FILE);
+        let mut store: ApiKeyStore = storage::load_json_file(&path)?;
+        store.path = path;
+        Ok(store)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        storage::save_json_file(&self.path, self)
+    }
+}
+
+/// Loads the API keys kept under `directory`. An unreadable file is reported
+/// at startup instead of failing the first request that needs a key.
+pub fn init(directory: &Path) -> io::Result<()> {
+    let store = ApiKeyStore::load(directory)?;
+    API_KEYS
+        .set(Mutex::new(store))
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "API keys already initialized"))
+}
+
+fn store() -> MutexGuard<'static, ApiKeyStore> {
+    API_KEYS
+        .get_or_init(|| Mutex::new(ApiKeyStore::load(Path::new(DATA_DIRECTORY)).expect("🛑 Failed to load API keys")))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Parses a comma or space separated scope list such as `read:balance,read:tickets`.
+pub fn parse_scopes(list: &str) -> Result<Vec<Scope>, String> {
+    let mut scopes = Vec::new();
+    for part in list.split([',', ' ']).filter(|part| !part.trim().is_empty()) {
+        let scope: Scope = part.parse()?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Err("no scopes given".to_string());
+    }
+    Ok(scopes)
+}
+
+/// Creates a key and returns it with its secret, `aw_<id>_<random>`.
+pub fn create_key(name: &str, scopes: Vec<Scope>, created_by: u64) -> io::Result<(ApiKey, String)> {
+    let mut store = store();
+    let id = loop {
+        let id = generate_seed(4);
+        if !store.keys.iter().any(|key| key.id == id) {
+            break id;
+        }
+    };
+    let secret = format!("{}_{}_{}", KEY_PREFIX, id, generate_seed(32));
+    let key = ApiKey {
+        id,
+        name: name.trim().to_string(),
+        key_hash: hash_seed(&secret),
+        scopes,
+        created_by,
+        created_at: current_unix_time(),
+        revoked_at: None,
+    };
+    store.keys.push(key.clone());
+    if let Err(e) = store.save() {
+        store.keys.pop();
+        return Err(e);
+    }
+    Ok((key, secret))
+}
+
+/// Revokes a key by id; returns `None` if no active key has that id.
+pub fn revoke_key(id: &str) -> io::Result<Option<ApiKey>> {
+    let mut store = store();
+    let Some(index) = store.keys.iter().position(|key| key.id == id && key.revoked_at.is_none()) else {
+        return Ok(None);
+    };
+    store.keys[index].revoked_at = Some(current_unix_time());
+    if let Err(e) = store.save() {
+        store.keys[index].revoked_at = None;
+        return Err(e);
+    }
+    Ok(Some(store.keys[index].clone()))
+}
+
+/// The active key matching `secret`, if any.
+pub fn authenticate(secret: &str) -> Option<ApiKey> {
+    let id = secret.strip_prefix(KEY_PREFIX)?.strip_prefix('_')?.split('_').next()?;
+    let key_hash = hash_seed(secret);
+    store().keys
+        .iter()
+        .find(|key| key.id == id && key.revoked_at.is_none() && key.key_hash == key_hash)
+        .cloned()
+}
+
+pub fn list_keys() -> Vec<ApiKey> {
+    store().keys.clone()
+}
+
+pub fn keys_summary() -> String {
+    let keys: Vec<ApiKey> = list_keys().into_iter().filter(|key| key.revoked_at.is_none()).collect();
+    if keys.is_empty() {
+        return "🔑 There are no active API keys.".to_string();
+    }
+    let mut lines = vec!["🔑 Active API keys".to_string()];
+    for key in keys {
+        let scopes: Vec<&str> = key.scopes.iter().map(|scope| scope.as_str()).collect();
+        lines.push(format!(
+            "`{}` {} [{}] created by <@{}> <t:{}:d>",
+            key.id,
+            key.name,
+            scopes.join(", "),
+            key.created_by,
+            key.created_at
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scopes() {
+        assert_eq!(parse_scopes("read:balance, read:tickets read:balance").unwrap(), vec![Scope::ReadBalance, Scope::ReadTickets]);
+        assert!(parse_scopes("read:everything").is_err());
+        assert!(parse_scopes(" , ").is_err());
+    }
+}
@@ -2,25 +2,80 @@ use serde::{Serialize, Deserialize};
 use std::fs;
 use std::io;
 use std::path::Path;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
-use rand::Rng;
 use serenity::prelude::*;
+use serenity::model::id::UserId;
 use serenity::model::user::User;
 
-const DATA_DIRECTORY: &str = "./data";
-const PACT_COOLDOWN: u64 = 86400;
+use std::collections::HashMap;
+use crate::config::{DailyConfig, PactConfig, StorageBackend, StorageConfig};
+use crate::fairness::{self, FairnessState};
+use crate::ledger::{EntryKind, LedgerEntry, LedgerMismatch};
+use crate::migrations::CURRENT_SCHEMA_VERSION;
+use crate::money::Money;
+use crate::storage::{self, JsonStorage, SqliteStorage, Storage};
+
+pub const DATA_DIRECTORY: &str = "./data";
+
+const LOCK_STRIPES: usize = 64;
+pub const HISTORY_PAGE_SIZE: usize = 10;
+pub const LEADERBOARD_DEFAULT_SIZE: usize = 10;
+pub const LEADERBOARD_MAX_SIZE: usize = 25;
+
+static STORAGE: OnceLock<Box<dyn Storage>> = OnceLock::new();
+static ACCOUNT_LOCKS: [Mutex<()>; LOCK_STRIPES] = [const { Mutex::new(()) }; LOCK_STRIPES];
 
 #[derive(Serialize, Deserialize)]
 pub struct UserData {
-    pub balance: f64,
+    /// Missing in records written before versioning, which are version 0.
+    #[serde(default)]
+    pub schema_version: u32,
+    pub balance: Money,
     pub last_pact: u64,
+    /// Set once the account has ledger history that accounts for `balance`.
+    #[serde(default)]
+    pub ledger_started: bool,
+    /// Frozen accounts cannot pay or enter pacts.
+    #[serde(default)]
+    pub frozen: bool,
+    /// Created on the first provably fair pact.
+    #[serde(default)]
+    pub fairness: Option<FairnessState>,
+    #[serde(default)]
+    pub last_daily: u64,
+    #[serde(default)]
+    pub daily_streak: u32,
+}
+
+/// A moderator change to someone else's account.
+pub enum AdminAction {
+    Grant(Money),
+    Revoke(Money),
+    Set(Money),
+    Freeze(bool),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    pub user_id: String,
+    pub balance: Money,
 }
 
 impl Default for UserData {
     fn default() -> Self {
         UserData {
-            balance: 0.0,
+            schema_version: CURRENT_SCHEMA_VERSION,
+            balance: Money::ZERO,
             last_pact: 0,
+            ledger_started: false,
+            frozen: false,
+            fairness: None,
+            last_daily: 0,
+            daily_streak: 0,
         }
     }
 }
@@ -32,104 +87,460 @@ pub fn ensure_data_directory() -> io::Result<()> {
     Ok(())
 }
 
-pub fn is_user_bound(user_id: &str) -> bool {
-    let file_path = format!("{}/{}.json", DATA_DIRECTORY, user_id);
-    Path::new(&file_path).exists()
+/// Selects the storage backend from config, with JSON user files kept in
+/// `directory`. Must run before the first balance operation, otherwise the
+/// JSON backend in `DATA_DIRECTORY` is used.
+pub fn init_storage(config: &StorageConfig, directory: &Path) -> io::Result<()> {
+    let backend: Box<dyn Storage> = match config.backend {
+        StorageBackend::Json => {
+            let json_storage = JsonStorage::new(directory);
+            json_storage.recover()?;
+            Box::new(json_storage)
+        }
+        StorageBackend::Sqlite => {
+            let sqlite = SqliteStorage::open(&config.sqlite_path)?;
+            if sqlite.is_empty()? {
+                let imported = sqlite.import_json(&JsonStorage::new(directory))?;
+                if imported > 0 {
+                    println!("📦 Imported {} user files from {} into {}", imported, directory.display(), config.sqlite_path);
+                }
+            }
+            Box::new(sqlite)
+        }
+    };
+    STORAGE
+        .set(backend)
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "storage already initialized"))
 }
 
-pub fn load_user_data(user_id: &str) -> UserData {
-    let file_path = format!("{}/{}.json", DATA_DIRECTORY, user_id);
-    if Path::new(&file_path).exists() {
-        if let Ok(data_str) = fs::read_to_string(&file_path) {
-            if let Ok(data) = serde_json::from_str::<UserData>(&data_str) {
-                return data;
+fn storage() -> &'static dyn Storage {
+    STORAGE
+        .get_or_init(|| {
+            let json_storage = JsonStorage::new(DATA_DIRECTORY);
+            if let Err(e) = json_storage.recover() {
+                eprintln!("🛑 Error recovering storage journal: {:?}", e);
             }
+            Box::new(json_storage)
+        })
+        .as_ref()
+}
+
+fn lock_stripe(user_id: &str) -> usize {
+    let mut hasher = DefaultHasher::new();
+    user_id.hash(&mut hasher);
+    (hasher.finish() as usize) % LOCK_STRIPES
+}
+
+/// Locks every given account for the lifetime of the returned guards.
+/// Stripes are always taken in ascending order, so callers cannot deadlock.
+fn lock_accounts(user_ids: &[&str]) -> Vec<MutexGuard<'static, ()>> {
+    let mut stripes: Vec<usize> = user_ids.iter().map(|id| lock_stripe(id)).collect();
+    stripes.sort_unstable();
+    stripes.dedup();
+    stripes
+        .into_iter()
+        .map(|stripe| ACCOUNT_LOCKS[stripe].lock().unwrap_or_else(|e| e.into_inner()))
+        .collect()
+}
+
+fn load_unlocked(user_id: &str) -> io::Result<UserData> {
+    Ok(storage().load(user_id)?.unwrap_or_default())
+}
+
+fn unreadable_account(e: io::Error) -> String {
+    format!("🛑 Account data could not be read ({}). please contact staff.", e)
+}
+
+/// Accounts that predate the ledger get an opening entry carrying their
+/// current balance, so that replaying the ledger reproduces it.
+fn open_ledger(user_id: &str, data: &mut UserData, entries: &mut Vec<LedgerEntry>) {
+    if !data.ledger_started {
+        entries.push(LedgerEntry::new(user_id, EntryKind::Opening, data.balance, data.balance, "system"));
+        data.ledger_started = true;
+    }
+}
+
+/// Newest-first slice of an account's ledger and the total number of entries.
+pub fn transaction_history(user_id: &str, offset: usize, limit: usize) -> io::Result<(Vec<LedgerEntry>, usize)> {
+    storage().ledger_page(user_id, offset, limit)
+}
+
+/// Ranked slice of all accounts by balance and the total number of accounts.
+pub fn leaderboard(limit: usize, offset: usize) -> io::Result<(Vec<LeaderboardEntry>, usize)> {
+    let (page, total) = storage().top_balances(limit, offset)?;
+    let entries = page
+        .into_iter()
+        .enumerate()
+        .map(|(i, (user_id, balance))| LeaderboardEntry { rank: offset + i + 1, user_id, balance })
+        .collect();
+    Ok((entries, total))
+}
+
+pub fn user_rank(user_id: &str) -> io::Result<Option<usize>> {
+    storage().rank(user_id)
+}
+
+/// Rebuilds every account balance by summing its ledger entries.
+pub fn replay_ledger() -> io::Result<HashMap<String, Money>> {
+    let mut balances: HashMap<String, Money> = HashMap::new();
+    for entry in storage().ledger(None)? {
+        let balance = balances.entry(entry.user_id).or_insert(Money::ZERO);
+        *balance = balance.checked_add(entry.amount).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("ledger entry {} overflows", entry.id))
+        })?;
+    }
+    Ok(balances)
+}
+
+/// Replays the ledger and reports every account whose stored balance differs.
+pub fn verify_ledger() -> io::Result<Vec<LedgerMismatch>> {
+    let mut mismatches = Vec::new();
+    for (user_id, replayed) in replay_ledger()? {
+        let stored = load_user_data(&user_id)?.balance;
+        if stored != replayed {
+            mismatches.push(LedgerMismatch { user_id, replayed, stored });
         }
     }
-    UserData::default()
+    Ok(mismatches)
 }
 
-pub fn save_user_data(user_id: &str, data: &UserData) -> io::Result<()> {
-    let file_path = format!("{}/{}.json", DATA_DIRECTORY, user_id);
-    let json_data = serde_json::to_string(data).unwrap_or_default();
-    fs::write(file_path, json_data)?;
-    Ok(())
+pub fn is_user_bound(user_id: &str) -> bool {
+    storage().exists(user_id).unwrap_or(false)
+}
+
+pub fn load_user_data(user_id: &str) -> io::Result<UserData> {
+    let _locks = lock_accounts(&[user_id]);
+    load_unlocked(user_id)
 }
 
-fn current_unix_time() -> u64 {
+pub fn current_unix_time() -> u64 {
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
 }
 
-pub fn perform_pact(user_id: &str, stake: f64) -> String {
-    let mut user_data = load_user_data(user_id);
+fn format_cooldown(remaining: u64) -> String {
+    let hours = remaining / 3600;
+    let minutes = (remaining % 3600) / 60;
+    let seconds = remaining % 60;
+    format!(
+        "🛑 Command already used. try again in {} h {} m {} s 🕔",
+        hours, minutes, seconds
+    )
+}
+
+pub fn perform_pact(user_id: &str, stake: Money, settings: &PactConfig) -> String {
+    let _locks = lock_accounts(&[user_id]);
+    let mut user_data = match load_unlocked(user_id) {
+        Ok(data) => data,
+        Err(e) => return unreadable_account(e),
+    };
     let now = current_unix_time();
 
-    if now.saturating_sub(user_data.last_pact) < PACT_COOLDOWN {
-        let remaining = PACT_COOLDOWN - (now - user_data.last_pact);
-        let hours = remaining / 3600;
-        let minutes = (remaining % 3600) / 60;
-        let seconds = remaining % 60;
-        return format!(
-            "🛑 Command already used. try again in {} h {} m {} s 🕔",
-            hours, minutes, seconds
-        );
+    if user_data.frozen {
+        return "🛑 Your account is frozen. please contact staff.".to_string();
+    }
+
+    if now.saturating_sub(user_data.last_pact) < settings.cooldown_secs {
+        return format_cooldown(settings.cooldown_secs - (now - user_data.last_pact));
     }
 
     if stake > user_data.balance {
         return format!(
-            "🛑 Your stake ({:.2}) 🪙 exceeds your available balance ({:.2}) 🪙",
+            "🛑 Your stake ({}) 🪙 exceeds your available balance ({}) 🪙",
             stake, user_data.balance
         );
     }
 
     user_data.last_pact = now;
+    let mut entries = Vec::new();
+    open_ledger(user_id, &mut user_data, &mut entries);
 
-    let mut rng = rand::thread_rng();
-    let roll: f64 = rng.gen_range(0.0..1.0);
-    if roll < 0.45 {
-        let bonus_percentage = rng.gen_range(7..=32);
-        let bonus_amount = stake * (bonus_percentage as f64 / 100.0);
-        user_data.balance += bonus_amount;
-        let _ = save_user_data(user_id, &user_data);
+    let state = settings.provably_fair
+        .then(|| user_data.fairness.get_or_insert_with(FairnessState::new));
+    let (roll, proof) = fairness::roll_pact(settings, state);
+    let proof_note = proof.as_ref()
+        .map(|proof| format!(" (nonce {}, seed hash `{}`)", proof.nonce, proof.server_seed_hash))
+        .unwrap_or_default();
+    if roll.win {
+        let bonus_percentage = roll.percent;
+        let bonus_amount = stake.percent(bonus_percentage);
+        let Some(new_balance) = user_data.balance.checked_add(bonus_amount) else {
+            return "🛑 Pact refused: your balance would overflow.".to_string();
+        };
+        user_data.balance = new_balance;
+        entries.push(
+            LedgerEntry::new(user_id, EntryKind::PactWin, bonus_amount, user_data.balance, user_id).with_proof(proof),
+        );
+        if let Err(e) = storage().commit(&[(user_id, &user_data)], &mut entries) {
+            return format!("🛑 Failed to save pact result: {:?}", e);
+        }
         format!(
-            "✅ Pact successful! bonus: +{}% (≈{:.2})🪙 New balance: {:.2} 🪙",
-            bonus_percentage, bonus_amount, user_data.balance
+            "✅ Pact successful! bonus: +{}% ({})🪙 New balance: {} 🪙{}",
+            bonus_percentage, bonus_amount, user_data.balance, proof_note
         )
     } else {
-        let penalty_percentage = rng.gen_range(7..=32);
-        let penalty_amount = stake * (penalty_percentage as f64 / 100.0);
-        user_data.balance -= penalty_amount;
-        let _ = save_user_data(user_id, &user_data);
+        let penalty_percentage = roll.percent;
+        let penalty_amount = stake.percent(penalty_percentage);
+        let Some(new_balance) = user_data.balance.checked_sub(penalty_amount) else {
+            return "🛑 Pact refused: your balance cannot cover the penalty.".to_string();
+        };
+        user_data.balance = new_balance;
+        entries.push(
+            LedgerEntry::new(user_id, EntryKind::PactLoss, penalty_amount.negate(), user_data.balance, user_id)
+                .with_proof(proof),
+        );
+        if let Err(e) = storage().commit(&[(user_id, &user_data)], &mut entries) {
+            return format!("🛑 Failed to save pact result: {:?}", e);
+        }
         format!(
-            "🛑 Pact failed – {}% (≈{:.2}) 🪙 lost. New balance: {:.2} 🪙",
-            penalty_percentage, penalty_amount, user_data.balance
+            "🛑 Pact failed – {}% ({}) 🪙 lost. New balance: {} 🪙{}",
+            penalty_percentage, penalty_amount, user_data.balance, proof_note
         )
     }
 }
 
+pub fn claim_daily(user_id: &str, settings: &DailyConfig) -> String {
+    let _locks = lock_accounts(&[user_id]);
+    let mut user_data = match load_unlocked(user_id) {
+        Ok(data) => data,
+        Err(e) => return unreadable_account(e),
+    };
+    let now = current_unix_time();
+
+    if user_data.frozen {
+        return "🛑 Your account is frozen. please contact staff.".to_string();
+    }
+
+    let since_last = now.saturating_sub(user_data.last_daily);
+    if since_last < settings.cooldown_secs {
+        return format_cooldown(settings.cooldown_secs - since_last);
+    }
+
+    let streak = if user_data.last_daily != 0 && since_last <= settings.streak_reset_secs {
+        user_data.daily_streak.saturating_add(1)
+    } else {
+        1
+    };
+    let bonus_days = streak.min(settings.max_streak.max(1)) - 1;
+    let Some(reward) = settings.streak_bonus.minor()
+        .checked_mul(bonus_days as i64)
+        .map(Money::from_minor)
+        .and_then(|bonus| settings.base_reward.checked_add(bonus))
+    else {
+        return "🛑 Daily reward is misconfigured.".to_string();
+    };
+    let Some(new_balance) = user_data.balance.checked_add(reward) else {
+        return "🛑 Daily reward refused: your balance would overflow.".to_string();
+    };
+
+    let mut entries = Vec::new();
+    open_ledger(user_id, &mut user_data, &mut entries);
+    user_data.balance = new_balance;
+    user_data.last_daily = now;
+    user_data.daily_streak = streak;
+    entries.push(LedgerEntry::new(user_id, EntryKind::DailyReward, reward, user_data.balance, user_id));
+    if let Err(e) = storage().commit(&[(user_id, &user_data)], &mut entries) {
+        return format!("🛑 Failed to save daily reward: {:?}", e);
+    }
+    format!(
+        "🎁 Daily reward: +{} 🪙 (streak: {} day(s)) New balance: {} 🪙",
+        reward, streak, user_data.balance
+    )
+}
+
+/// Shows the committed server seed hash and current client seed.
+pub fn fairness_status(user_id: &str) -> String {
+    let _locks = lock_accounts(&[user_id]);
+    if !is_user_bound(user_id) {
+        return "🛑 Your account is not bound. please use /bind first.".to_string();
+    }
+    let mut user_data = match load_unlocked(user_id) {
+        Ok(data) => data,
+        Err(e) => return unreadable_account(e),
+    };
+    if user_data.fairness.is_none() {
+        user_data.fairness = Some(FairnessState::new());
+        if let Err(e) = storage().commit(&[(user_id, &user_data)], &mut []) {
+            return format!("🛑 Failed to create seeds: {:?}", e);
+        }
+    }
+    let state = user_data.fairness.as_ref().expect("fairness state was just created");
+    let mut lines = vec![
+        "🎲 Provably fair pacts".to_string(),
+        format!("Server seed hash: `{}`", state.server_seed_hash),
+        format!("Client seed: `{}`", state.client_seed),
+        format!("Next nonce: {}", state.nonce),
+    ];
+    for revealed in state.revealed.iter().rev().take(3) {
+        lines.push(format!(
+            "Revealed: `{}` (hash `{}`, {} roll(s))",
+            revealed.server_seed, revealed.server_seed_hash, revealed.rolls
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Replaces the client seed; later rolls use it from nonce 0 of a new server seed.
+pub fn set_client_seed(user_id: &str, client_seed: &str) -> String {
+    let client_seed = client_seed.trim();
+    if client_seed.is_empty() || client_seed.len() > 64 {
+        return "🛑 The client seed must be between 1 and 64 characters.".to_string();
+    }
+    let _locks = lock_accounts(&[user_id]);
+    if !is_user_bound(user_id) {
+        return "🛑 Your account is not bound. please use /bind first.".to_string();
+    }
+    let mut user_data = match load_unlocked(user_id) {
+        Ok(data) => data,
+        Err(e) => return unreadable_account(e),
+    };
+    let state = user_data.fairness.get_or_insert_with(FairnessState::new);
+    let revealed = (state.nonce > 0).then(|| state.rotate());
+    state.client_seed = client_seed.to_string();
+    let new_hash = state.server_seed_hash.clone();
+    if let Err(e) = storage().commit(&[(user_id, &user_data)], &mut []) {
+        return format!("🛑 Failed to update client seed: {:?}", e);
+    }
+    let mut message = format!("✅ Client seed updated. new server seed hash: `{}`", new_hash);
+    if let Some(revealed) = revealed {
+        message.push_str(&format!("\nℹ️ Previous server seed revealed: `{}`", revealed.server_seed));
+    }
+    message
+}
+
+/// Reveals the current server seed and commits to a new one.
+pub fn rotate_server_seed(user_id: &str) -> String {
+    let _locks = lock_accounts(&[user_id]);
+    if !is_user_bound(user_id) {
+        return "🛑 Your account is not bound. please use /bind first.".to_string();
+    }
+    let mut user_data = match load_unlocked(user_id) {
+        Ok(data) => data,
+        Err(e) => return unreadable_account(e),
+    };
+    let state = user_data.fairness.get_or_insert_with(FairnessState::new);
+    let revealed = state.rotate();
+    let new_hash = state.server_seed_hash.clone();
+    if let Err(e) = storage().commit(&[(user_id, &user_data)], &mut []) {
+        return format!("🛑 Failed to rotate server seed: {:?}", e);
+    }
+    format!(
+        "✅ Server seed revealed: `{}` (hash `{}`, {} roll(s))\nℹ️ New server seed hash: `{}`",
+        revealed.server_seed, revealed.server_seed_hash, revealed.rolls, new_hash
+    )
+}
+
+/// Recomputes a roll from revealed inputs so anyone can check a past pact.
+/// A pact found in the ledger is rolled with the odds recorded for it, so
+/// later config changes do not break old proofs.
+pub fn verify_pact_roll(server_seed: &str, client_seed: &str, nonce: u64, settings: &PactConfig) -> String {
+    let server_seed_hash = fairness::hash_seed(server_seed);
+    let recorded = match storage().pact_entry(&server_seed_hash, nonce) {
+        Ok(entry) => entry.filter(|entry| entry.proof.as_ref().is_some_and(|proof| proof.client_seed == client_seed)),
+        Err(e) => return format!("🛑 Failed to load the ledger: {:?}", e),
+    };
+    let (win_chance, min_percent, max_percent) = match recorded.as_ref().and_then(|entry| entry.proof.as_ref()) {
+        Some(proof) => (proof.win_chance, proof.min_percent, proof.max_percent),
+        None => (settings.win_chance, settings.min_percent, settings.max_percent),
+    };
+    let roll = fairness::fair_roll(server_seed, client_seed, nonce, win_chance, min_percent, max_percent);
+    let mut message = format!(
+        "🎲 Seed hash: `{}`\nRoll: {:.6} → {} {}% (win chance {:.2}, range {}-{}%)",
+        server_seed_hash,
+        roll.roll,
+        if roll.win { "win" } else { "loss" },
+        roll.percent,
+        win_chance,
+        min_percent,
+        max_percent
+    );
+    match recorded {
+        Some(entry) if (entry.kind == EntryKind::PactWin) == roll.win => {
+            message.push_str(&format!("\n✅ Matches pact `#{}`", entry.id));
+        }
+        Some(entry) => message.push_str(&format!("\n🛑 Does not match pact `#{}` ({})", entry.id, entry.kind.label())),
+        None => message.push_str("\nℹ️ No recorded pact uses these seeds, so the current odds were used."),
+    }
+    message
+}
+
 pub fn bind_user(user_id: &str) -> String {
-    let file_path = format!("{}/{}.json", DATA_DIRECTORY, user_id);
-    if Path::new(&file_path).exists() {
-        let _ = load_user_data(user_id).balance;
+    let _locks = lock_accounts(&[user_id]);
+    if is_user_bound(user_id) {
         return "🛑 Account already bound.".to_string();
     }
-    let user_data = UserData::default();
-    match save_user_data(user_id, &user_data) {
+    let user_data = UserData {
+        ledger_started: true,
+        ..UserData::default()
+    };
+    let mut entries = vec![LedgerEntry::new(user_id, EntryKind::Bind, Money::ZERO, Money::ZERO, user_id)];
+    match storage().commit(&[(user_id, &user_data)], &mut entries) {
         Ok(_) => "✅ Account bound successfully.".to_string(),
         Err(e) => format!("🛑 Error binding account: {:?}", e),
     }
 }
 
 pub fn get_balance(user_id: &str) -> String {
-    let balance = load_user_data(user_id).balance;
-    format!("ℹ️ Your balance: {:.2} 🪙", balance)
+    match load_user_data(user_id) {
+        Ok(user_data) => format!("ℹ️ Your balance: {} 🪙", user_data.balance),
+        Err(e) => unreadable_account(e),
+    }
 }
 
-pub fn pay(from_user: &str, to_user: &str, amount: f64) -> String {
+pub fn get_history(user_id: &str, page: usize) -> String {
+    let page = page.max(1);
+    let (entries, total) = match transaction_history(user_id, (page - 1) * HISTORY_PAGE_SIZE, HISTORY_PAGE_SIZE) {
+        Ok(history) => history,
+        Err(e) => return format!("🛑 Failed to load transaction history: {:?}", e),
+    };
+    if entries.is_empty() {
+        return if total == 0 {
+            "ℹ️ No transactions yet.".to_string()
+        } else {
+            format!("🛑 Page {} does not exist.", page)
+        };
+    }
+    let pages = total.div_ceil(HISTORY_PAGE_SIZE);
+    let mut lines = vec![format!("📜 Transaction history (page {}/{})", page, pages)];
+    for entry in entries {
+        let counterparty = entry.counterparty
+            .map(|id| format!(" · <@{}>", id))
+            .unwrap_or_default();
+        let reason = entry.reason
+            .map(|reason| format!(" · {}", reason))
+            .unwrap_or_default();
+        lines.push(format!(
+            "`#{}` <t:{}:f> {}: {} 🪙 → {} 🪙{}{}",
+            entry.id, entry.timestamp, entry.kind.label(), entry.amount, entry.balance_after, counterparty, reason
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn get_leaderboard(user_id: &str, limit: usize) -> String {
+    let (entries, total) = match leaderboard(limit.clamp(1, LEADERBOARD_MAX_SIZE), 0) {
+        Ok(leaderboard) => leaderboard,
+        Err(e) => return format!("🛑 Failed to load leaderboard: {:?}", e),
+    };
+    if entries.is_empty() {
+        return "ℹ️ Nobody is on the leaderboard yet.".to_string();
+    }
+    let mut lines = vec!["🏆 Leaderboard".to_string()];
+    for entry in entries {
+        lines.push(format!("`#{}` <@{}> — {} 🪙", entry.rank, entry.user_id, entry.balance));
+    }
+    match user_rank(user_id) {
+        Ok(Some(rank)) => lines.push(format!("ℹ️ Your rank: #{} of {}", rank, total)),
+        Ok(None) => lines.push("ℹ️ You are not ranked. please use /bind first.".to_string()),
+        Err(e) => eprintln!("🛑 Failed to load rank of {}: {:?}", user_id, e),
+    }
+    lines.join("\n")
+}
+
+pub fn pay(from_user: &str, to_user: &str, amount: Money) -> String {
     if from_user == to_user {
         return "🛑 You cannot pay yourself.".to_string();
     }
-    if amount <= 0.0 {
+    if !amount.is_positive() {
         return "🛑 The amount must be greater than zero.".to_string();
     }
     if !is_user_bound(from_user) {
@@ -138,35 +549,128 @@ pub fn pay(from_user: &str, to_user: &str, amount: f64) -> String {
     if !is_user_bound(to_user) {
         return "🛑 Recipient account is not bound.".to_string();
     }
-    let mut sender_data = load_user_data(from_user);
-    if amount > sender_data.balance {
+    let _locks = lock_accounts(&[from_user, to_user]);
+    let mut sender_data = match load_unlocked(from_user) {
+        Ok(data) => data,
+        Err(e) => return unreadable_account(e),
+    };
+    let Some(sender_balance) = sender_data.balance.checked_sub(amount) else {
         return format!(
-            "🛑 Insufficient funds: your balance is {:.2}",
+            "🛑 Insufficient funds: your balance is {}",
             sender_data.balance
         );
+    };
+    if sender_data.frozen {
+        return "🛑 Your account is frozen. please contact staff.".to_string();
+    }
+    let mut recipient_data = match load_unlocked(to_user) {
+        Ok(data) => data,
+        Err(e) => return unreadable_account(e),
+    };
+    if recipient_data.frozen {
+        return "🛑 Recipient account is frozen.".to_string();
     }
-    let mut recipient_data = load_user_data(to_user);
+    let Some(recipient_balance) = recipient_data.balance.checked_add(amount) else {
+        return "🛑 Transfer refused: the recipient balance would overflow.".to_string();
+    };
 
-    sender_data.balance -= amount;
-    recipient_data.balance += amount;
+    let mut entries = Vec::new();
+    open_ledger(from_user, &mut sender_data, &mut entries);
+    open_ledger(to_user, &mut recipient_data, &mut entries);
 
-    if let Err(e) = save_user_data(from_user, &sender_data) {
-        return format!("🛑 Failed to update sender data: {:?}", e);
-    }
-    if let Err(e) = save_user_data(to_user, &recipient_data) {
-        sender_data.balance += amount;
-        let _ = save_user_data(from_user, &sender_data);
-        return format!("🛑 Failed to update recipient data: {:?}", e);
+    sender_data.balance = sender_balance;
+    recipient_data.balance = recipient_balance;
+    entries.push(
+        LedgerEntry::new(from_user, EntryKind::PaymentSent, amount.negate(), sender_data.balance, from_user)
+            .with_counterparty(to_user),
+    );
+    entries.push(
+        LedgerEntry::new(to_user, EntryKind::PaymentReceived, amount, recipient_data.balance, from_user)
+            .with_counterparty(from_user),
+    );
+
+    if let Err(e) = storage().commit(&[(from_user, &sender_data), (to_user, &recipient_data)], &mut entries) {
+        if storage::is_commit_pending(&e) {
+            eprintln!("🛑 Transfer from {} to {} is pending: {:?}", from_user, to_user, e);
+            return "🛑 Transfer was recorded but not fully saved. it will be completed automatically, please check /balance before trying again.".to_string();
+        }
+        return format!("🛑 Transfer failed, no coins were moved: {:?}", e);
     }
 
     format!(
-        "✅ Successfully transferred {:.2} 🪙 to {}",
+        "✅ Successfully transferred {} 🪙 to {}",
         amount, to_user,
     )
 }
 
-pub async fn perform_pact_dm(ctx: &Context, user: &User, stake: f64) -> Result<(), serenity::Error> {
-    let result = perform_pact(&user.id.to_string(), stake);
+pub fn admin_adjust(target: &str, action: AdminAction, actor: &str, reason: &str) -> String {
+    if reason.trim().is_empty() {
+        return "🛑 A reason is required.".to_string();
+    }
+    match action {
+        AdminAction::Grant(amount) | AdminAction::Revoke(amount) if !amount.is_positive() => {
+            return "🛑 Amount must be greater than zero.".to_string();
+        }
+        AdminAction::Set(amount) if amount < Money::ZERO => {
+            return "🛑 Balance cannot be negative.".to_string();
+        }
+        _ => {}
+    }
+    if !is_user_bound(target) {
+        return "🛑 Target account is not bound.".to_string();
+    }
+    let _locks = lock_accounts(&[target]);
+    let mut user_data = match load_unlocked(target) {
+        Ok(data) => data,
+        Err(e) => return unreadable_account(e),
+    };
+    let mut entries = Vec::new();
+    open_ledger(target, &mut user_data, &mut entries);
+
+    let old_balance = user_data.balance;
+    let (kind, summary) = match action {
+        AdminAction::Grant(amount) => {
+            let Some(new_balance) = old_balance.checked_add(amount) else {
+                return "🛑 Grant refused: the balance would overflow.".to_string();
+            };
+            user_data.balance = new_balance;
+            (EntryKind::AdminGrant, format!("✅ Granted {} 🪙 to <@{}>", amount, target))
+        }
+        AdminAction::Revoke(amount) => {
+            let Some(new_balance) = old_balance.checked_sub(amount) else {
+                return format!("🛑 Cannot revoke {} 🪙: balance is only {} 🪙", amount, old_balance);
+            };
+            user_data.balance = new_balance;
+            (EntryKind::AdminRevoke, format!("✅ Revoked {} 🪙 from <@{}>", amount, target))
+        }
+        AdminAction::Set(amount) => {
+            user_data.balance = amount;
+            (EntryKind::AdminSet, format!("✅ Set balance of <@{}> to {} 🪙", target, amount))
+        }
+        AdminAction::Freeze(frozen) => {
+            if user_data.frozen == frozen {
+                return format!("🛑 Account is already {}.", if frozen { "frozen" } else { "unfrozen" });
+            }
+            user_data.frozen = frozen;
+            if frozen {
+                (EntryKind::AdminFreeze, format!("✅ Froze the account of <@{}>", target))
+            } else {
+                (EntryKind::AdminUnfreeze, format!("✅ Unfroze the account of <@{}>", target))
+            }
+        }
+    };
+
+    let delta = Money::from_minor(user_data.balance.minor() - old_balance.minor());
+    entries.push(LedgerEntry::new(target, kind, delta, user_data.balance, actor).with_reason(reason));
+    if let Err(e) = storage().commit(&[(target, &user_data)], &mut entries) {
+        return format!("🛑 Failed to update account: {:?}", e);
+    }
+    println!("🛡️ {} by {} on {}: {}", kind.label(), actor, target, reason);
+    format!("{} (new balance: {} 🪙)", summary, user_data.balance)
+}
+
+pub async fn perform_pact_dm(ctx: &Context, user: &User, stake: Money, settings: &PactConfig) -> Result<(), serenity::Error> {
+    let result = perform_pact(&user.id.to_string(), stake, settings);
     if let Ok(dm_channel) = user.create_dm_channel(&ctx.http).await {
         let _ = dm_channel.say(&ctx.http, result).await;
     }
@@ -189,23 +693,167 @@ pub async fn get_balance_dm(ctx: &Context, user: &User) -> Result<(), serenity::
     Ok(())
 }
 
-pub async fn pay_dm(ctx: &Context, sender: &User, recipient: &User, amount: f64) -> Result<(), serenity::Error> {
-    let result = pay(&sender.id.to_string(), &recipient.id.to_string(), amount);
+pub async fn pay_dm(ctx: &Context, sender: &User, recipient: UserId, amount: Money) -> Result<(), serenity::Error> {
+    let result = pay(&sender.id.to_string(), &recipient.to_string(), amount);
+
+    if !result.starts_with("✅") {
+        if let Ok(dm_channel) = sender.create_dm_channel(&ctx.http).await {
+            let _ = dm_channel.say(&ctx.http, result).await;
+        }
+        return Ok(());
+    }
 
     if let Ok(dm_channel) = sender.create_dm_channel(&ctx.http).await {
-        let _ = dm_channel.say(&ctx.http, result.clone()).await;
+        let dm_message = format!(
+            "✅ Successfully transferred {} 🪙 to {}",
+            amount,
+            recipient.mention()
+        );
+        let _ = dm_channel.say(&ctx.http, dm_message).await;
     }
 
-    if result.starts_with("✅") {
-        if let Ok(dm_channel) = recipient.create_dm_channel(&ctx.http).await {
-            let dm_message = format!(
-                "💸 You have received {:.2} 🪙 from {}",
-                amount,
-                sender.mention()
-            );
-            let _ = dm_channel.say(&ctx.http, dm_message).await;
-        }
+    if let Ok(dm_channel) = recipient.create_dm_channel(&ctx.http).await {
+        let dm_message = format!(
+            "💸 You have received {} 🪙 from {}",
+            amount,
+            sender.mention()
+        );
+        let _ = dm_channel.say(&ctx.http, dm_message).await;
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Points the global storage at a scratch directory so tests never write
+/// into `./data`. Safe to call from every test.
+#[cfg(test)]
+pub fn init_test_storage() {
+    let directory = std::env::temp_dir().join(format!("arcwarden-balance-{}", std::process::id()));
+    fs::create_dir_all(&directory).expect("🛑 Error creating test data directory");
+    let _ = STORAGE.set(Box::new(JsonStorage::new(directory)));
+}
+
+/// Binds `user_id` in the test storage and sets its balance, with a ledger
+/// entry that accounts for the change.
+#[cfg(test)]
+pub fn fund_test_account(user_id: &str, coins: &str) {
+    let balance: Money = coins.parse().expect("🛑 Invalid test amount");
+    init_test_storage();
+    bind_user(user_id);
+    let _locks = lock_accounts(&[user_id]);
+    let mut user_data = load_unlocked(user_id).expect("🛑 Error loading test account");
+    let mut entries = vec![LedgerEntry::new(user_id, EntryKind::Opening, balance.checked_add(user_data.balance.negate()).unwrap(), balance, "test")];
+    user_data.balance = balance;
+    storage().commit(&[(user_id, &user_data)], &mut entries).expect("🛑 Error funding test account");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_pay_keeps_totals() {
+        let accounts = ["700000000000000001", "700000000000000002", "700000000000000003"];
+        for account in accounts {
+            fund_test_account(account, "100");
+        }
+        // Every account pays the next one in a ring, so balances end where they started.
+        let handles: Vec<_> = (0..accounts.len())
+            .map(|i| {
+                let (from, to) = (accounts[i], accounts[(i + 1) % accounts.len()]);
+                thread::spawn(move || {
+                    for _ in 0..25 {
+                        assert!(pay(from, to, "1.5".parse().unwrap()).starts_with("✅"));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        for account in accounts {
+            assert_eq!(load_user_data(account).unwrap().balance, "100".parse().unwrap());
+            let ledger = storage().ledger(Some(account)).unwrap();
+            assert_eq!(ledger.last().unwrap().balance_after, "100".parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_refused_pay_moves_nothing() {
+        let (from, to) = ("700000000000000011", "700000000000000012");
+        fund_test_account(from, "5");
+        fund_test_account(to, "0");
+        let entries_before = storage().ledger(Some(from)).unwrap().len();
+        assert!(pay(from, to, "5.01".parse().unwrap()).starts_with("🛑 Insufficient funds"));
+        assert_eq!(load_user_data(from).unwrap().balance, "5".parse().unwrap());
+        assert_eq!(load_user_data(to).unwrap().balance, Money::ZERO);
+        assert_eq!(storage().ledger(Some(from)).unwrap().len(), entries_before);
+    }
+
+    #[test]
+    fn test_verify_ledger() {
+        let (first, second) = ("700000000000000021", "700000000000000022");
+        fund_test_account(first, "40");
+        fund_test_account(second, "2.5");
+        pay(first, second, "12.25".parse().unwrap());
+
+        let replayed = replay_ledger().unwrap();
+        assert_eq!(replayed[first], "27.75".parse().unwrap());
+        assert_eq!(replayed[second], "14.75".parse().unwrap());
+        assert!(!verify_ledger().unwrap().iter().any(|mismatch| mismatch.user_id == first || mismatch.user_id == second));
+
+        // A balance changed without a ledger entry is reported.
+        let locks = lock_accounts(&[second]);
+        let mut data = load_unlocked(second).unwrap();
+        data.balance = "99".parse().unwrap();
+        storage().commit(&[(second, &data)], &mut []).unwrap();
+        drop(locks);
+        let mismatch = verify_ledger().unwrap().into_iter().find(|mismatch| mismatch.user_id == second).unwrap();
+        assert_eq!(mismatch.replayed, "14.75".parse().unwrap());
+        assert_eq!(mismatch.stored, "99".parse().unwrap());
+    }
+
+    #[test]
+    fn test_admin_adjust_rejects_non_positive_amounts() {
+        let target = "700000000000000031";
+        fund_test_account(target, "10");
+        for action in [AdminAction::Grant(Money::ZERO), AdminAction::Revoke(Money::from_minor(-100))] {
+            assert!(admin_adjust(target, action, "mod", "test").starts_with("🛑"));
+        }
+        assert!(admin_adjust(target, AdminAction::Set(Money::from_minor(-100)), "mod", "test").starts_with("🛑"));
+        assert_eq!(load_user_data(target).unwrap().balance, "10".parse().unwrap());
+
+        assert!(admin_adjust(target, AdminAction::Revoke("2.5".parse().unwrap()), "mod", "test").starts_with("✅"));
+        assert_eq!(load_user_data(target).unwrap().balance, "7.5".parse().unwrap());
+    }
+
+    #[test]
+    fn test_verify_uses_recorded_odds() {
+        let user_id = "700000000000000041";
+        fund_test_account(user_id, "100");
+        let mut settings = PactConfig { provably_fair: true, cooldown_secs: 0, ..PactConfig::default() };
+        perform_pact(user_id, "10".parse().unwrap(), &settings);
+        rotate_server_seed(user_id);
+        let state = load_user_data(user_id).unwrap().fairness.unwrap();
+        let revealed = state.revealed.last().unwrap();
+
+        // Odds changed after the roll still verify against the recorded ones.
+        settings.win_chance = 0.0;
+        settings.min_percent = 90;
+        settings.max_percent = 95;
+        let result = verify_pact_roll(&revealed.server_seed, &state.client_seed, 0, &settings);
+        assert!(result.contains("✅ Matches pact"), "{}", result);
+        assert!(result.contains("range 7-32%"), "{}", result);
+    }
+
+    #[test]
+    fn test_fairness_requires_bound_account() {
+        let user_id = "700000000000000051";
+        init_test_storage();
+        assert!(fairness_status(user_id).starts_with("🛑"));
+        assert!(set_client_seed(user_id, "mine").starts_with("🛑"));
+        assert!(rotate_server_seed(user_id).starts_with("🛑"));
+        assert!(!is_user_bound(user_id));
+    }
+}
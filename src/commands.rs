@@ -1,26 +1,180 @@
 use serenity::prelude::*;
 use serenity::model::prelude::*;
-use serenity::builder::{CreateChannel, CreateCommand, CreateCommandOption};
+use serenity::builder::{
+    CreateActionRow, CreateAttachment, CreateButton, CreateChannel, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateInputText, CreateMessage, CreateModal, CreateSelectMenu, CreateSelectMenuKind, EditChannel,
+};
 use serenity::model::channel::ChannelType;
 use serenity::model::Permissions;
+use serenity::http::HttpError;
 use serenity::all::{CommandOptionType};
-use std::sync::atomic::{AtomicU32, Ordering};
 
-static LAST_TICKET_ID: AtomicU32 = AtomicU32::new(10000);
+use crate::balance;
+use crate::config::{self, CloseMode, Config, QuestionStyle, TicketCloseConfig, TicketType};
+use crate::tickets::{self, CloseReason, IntakeAnswer, Ticket, TicketStatus};
+use crate::transcript::{self, RenderedTranscript};
 
-fn generate_ticket_id() -> u32 {
-    LAST_TICKET_ID.fetch_add(1, Ordering::Relaxed)
+/// Followed by `:<ticket type>` in the custom id of each panel button.
+pub const OPEN_TICKET_BUTTON: &str = "ticket_open";
+/// Followed by `:<ticket type>` in the custom id of each intake modal.
+pub const INTAKE_MODAL: &str = "ticket_intake";
+pub const CLOSE_TICKET_BUTTON: &str = "ticket_close";
+pub const CLAIM_TICKET_BUTTON: &str = "ticket_claim";
+pub const ADD_USER_BUTTON: &str = "ticket_add_user";
+pub const ADD_USER_SELECT: &str = "ticket_add_user_select";
+pub const KEEP_OPEN_BUTTON: &str = "ticket_keep_open";
+/// Followed by `:<ticket id>:<stars>`.
+pub const RATE_TICKET_BUTTON: &str = "ticket_rate";
+/// Followed by `:<ticket id>` for both the button and the modal it opens.
+pub const FEEDBACK_BUTTON: &str = "ticket_feedback";
+pub const FEEDBACK_MODAL: &str = "ticket_feedback_modal";
+
+/// Buttons posted at the top of every ticket channel.
+fn ticket_controls() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(CLOSE_TICKET_BUTTON).label("Close").emoji('📁').style(ButtonStyle::Danger),
+        CreateButton::new(CLAIM_TICKET_BUTTON).label("Claim").emoji('🙋').style(ButtonStyle::Primary),
+        CreateButton::new(ADD_USER_BUTTON).label("Add user").emoji('➕').style(ButtonStyle::Secondary),
+    ])
+}
+
+pub fn add_user_menu() -> CreateActionRow {
+    CreateActionRow::SelectMenu(
+        CreateSelectMenu::new(ADD_USER_SELECT, CreateSelectMenuKind::User { default_users: None })
+            .placeholder("Members to add")
+            .max_values(5)
+    )
+}
+
+/// Longest answer that still fits in an embed field.
+const MAX_ANSWER_LENGTH: u16 = 1024;
+
+pub fn intake_modal(ticket_type: &TicketType) -> CreateModal {
+    let inputs = ticket_type.questions
+        .iter()
+        .enumerate()
+        .map(|(index, question)| {
+            let style = match question.style {
+                QuestionStyle::Short => InputTextStyle::Short,
+                QuestionStyle::Paragraph => InputTextStyle::Paragraph,
+            };
+            let mut input = CreateInputText::new(style, &question.label, format!("question_{}", index))
+                .required(question.required)
+                .max_length(MAX_ANSWER_LENGTH);
+            if let Some(placeholder) = &question.placeholder {
+                input = input.placeholder(placeholder);
+            }
+            CreateActionRow::InputText(input)
+        })
+        .collect();
+    CreateModal::new(format!("{}:{}", INTAKE_MODAL, ticket_type.name), ticket_type.modal_title())
+        .components(inputs)
+}
+
+/// Pairs the submitted values of an intake modal with their questions.
+pub fn intake_answers(ticket_type: &TicketType, rows: &[ActionRow]) -> Vec<IntakeAnswer> {
+    rows.iter()
+        .flat_map(|row| row.components.iter())
+        .filter_map(|component| match component {
+            ActionRowComponent::InputText(input) => {
+                let index: usize = input.custom_id.strip_prefix("question_")?.parse().ok()?;
+                let question = ticket_type.questions.get(index)?;
+                Some(IntakeAnswer {
+                    question: question.label.clone(),
+                    answer: input.value.clone().unwrap_or_default(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+pub async fn post_ticket_panel(
+    ctx: &Context,
+    channel_id: ChannelId,
+    ticket_types: &[TicketType],
+) -> Result<Message, serenity::Error> {
+    let embed = CreateEmbed::new()
+        .title("📍 Support tickets")
+        .description("Pick the kind of ticket below to open a private channel with the moderators.")
+        .color(0x5865F2);
+    // Discord allows five buttons per row and five rows per message.
+    let rows = ticket_types
+        .chunks(5)
+        .take(5)
+        .map(|chunk| {
+            CreateActionRow::Buttons(chunk.iter()
+                .map(|ticket_type| {
+                    CreateButton::new(format!("{}:{}", OPEN_TICKET_BUTTON, ticket_type.name))
+                        .label(ticket_type.label())
+                        .emoji('📍')
+                        .style(ButtonStyle::Primary)
+                })
+                .collect())
+        })
+        .collect();
+    channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed).components(rows)).await
+}
+
+/// Read and write access for a single member of a ticket.
+fn member_overwrite(user_id: UserId) -> PermissionOverwrite {
+    PermissionOverwrite {
+        kind: PermissionOverwriteType::Member(user_id),
+        allow: Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
+        deny: Permissions::empty(),
+    }
 }
 
+/// Moderator roles always see a ticket but may only write while it is unclaimed.
+fn staff_overwrite(role_id: RoleId, can_send: bool) -> PermissionOverwrite {
+    if can_send {
+        PermissionOverwrite {
+            kind: PermissionOverwriteType::Role(role_id),
+            allow: Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
+            deny: Permissions::empty(),
+        }
+    } else {
+        PermissionOverwrite {
+            kind: PermissionOverwriteType::Role(role_id),
+            allow: Permissions::VIEW_CHANNEL,
+            deny: Permissions::SEND_MESSAGES,
+        }
+    }
+}
+
+/// Discord refuses to put more channels than this in one category.
+const MAX_CATEGORY_CHANNELS: usize = 50;
+
+/// The first of `category_ids` that still has room for another channel.
+async fn pick_ticket_category(ctx: &Context, guild_id: GuildId, category_ids: &[u64]) -> Result<ChannelId, serenity::Error> {
+    let channels = guild_id.channels(&ctx.http).await?;
+    for &category_id in category_ids {
+        let category = ChannelId::new(category_id);
+        let used = channels.values().filter(|channel| channel.parent_id == Some(category)).count();
+        if used < MAX_CATEGORY_CHANNELS {
+            return Ok(category);
+        }
+        println!("ℹ️ Ticket category {} is full, trying the next one", category);
+    }
+    Err(std::io::Error::other("every ticket category is full").into())
+}
+
+/// `ticket_id` comes from `tickets::reserve_id` and is released again if the
+/// channel cannot be created. A full category spills over into the next one
+/// from `config::get_overflow_categories`.
 pub async fn create_ticket_channel(
     ctx: &Context,
     guild_id: GuildId,
     initiator: UserId,
-    ticket_category_id: ChannelId,
-    mod_role_ids: &[RoleId],
+    ticket_id: u32,
+    ticket_type: &TicketType,
+    config: &Config,
+    intake: Vec<IntakeAnswer>,
 ) -> Result<ChannelId, serenity::Error> {
-    let ticket_id = generate_ticket_id();
-    let channel_name = format!("ticket-{}", ticket_id);
+    let category_ids = config::get_overflow_categories(config, ticket_type);
+    let category = pick_ticket_category(ctx, guild_id, &category_ids).await
+        .inspect_err(|_| tickets::release_id(ticket_id))?;
+    let channel_name = format!("{}-{}", ticket_type.channel_prefix, ticket_id);
     println!("✅ Creating ticket: ID #{} for user: {}", ticket_id, initiator);
 
     let mut overwrites = Vec::new();
@@ -29,35 +183,266 @@ pub async fn create_ticket_channel(
         allow: Permissions::empty(),
         deny: Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
     });
-    overwrites.push(PermissionOverwrite {
-        kind: PermissionOverwriteType::Member(initiator),
-        allow: Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
-        deny: Permissions::empty(),
-    });
-    for mod_role in mod_role_ids {
-        overwrites.push(PermissionOverwrite {
-            kind: PermissionOverwriteType::Role(*mod_role),
-            allow: Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
-            deny: Permissions::empty(),
-        });
+    overwrites.push(member_overwrite(initiator));
+    for mod_role in config::get_ticket_roles(config, Some(&ticket_type.name)) {
+        overwrites.push(staff_overwrite(mod_role, true));
     }
-    overwrites.push(PermissionOverwrite {
-        kind: PermissionOverwriteType::Member(ctx.cache.current_user().id),
-        allow: Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES,
-        deny: Permissions::empty(),
-    });
+    overwrites.push(member_overwrite(ctx.cache.current_user().id));
 
     let create_channel = CreateChannel::new(channel_name)
         .kind(ChannelType::Text)
-        .category(ticket_category_id)
+        .category(category)
         .permissions(overwrites);
-    let new_channel = guild_id.create_channel(&ctx.http, create_channel).await?;
+    let new_channel = guild_id.create_channel(&ctx.http, create_channel).await
+        .inspect_err(|_| tickets::release_id(ticket_id))?;
+    let intake_embed = (!intake.is_empty()).then(|| {
+        CreateEmbed::new()
+            .title("📝 Intake form")
+            .color(0x5865F2)
+            .fields(intake.iter().map(|answer| {
+                let value = if answer.answer.trim().is_empty() { "—".to_string() } else { answer.answer.clone() };
+                (answer.question.clone(), value, false)
+            }))
+    });
+    tickets::record_open(ticket_id, new_channel.id.get(), category.get(), initiator.get(), &ticket_type.name, intake)?;
     println!("✅ Ticket created: {} (channel ID: {})", new_channel.name, new_channel.id);
+
+    let mut welcome = CreateMessage::new()
+        .content(format!("📍 <@{}> {}", initiator, ticket_type.welcome_message))
+        .components(vec![ticket_controls()]);
+    if let Some(embed) = intake_embed {
+        welcome = welcome.embed(embed);
+    }
+    if let Err(e) = new_channel.id.send_message(&ctx.http, welcome).await {
+        eprintln!("🛑 Failed to post ticket controls in {}: {:?}", new_channel.id, e);
+    }
     Ok(new_channel.id)
 }
 
-pub async fn close_ticket(ctx: &Context, channel_id: ChannelId) -> Result<(), serenity::Error> {
+/// Posts the transcript of a ticket to the log channel and DMs it to the opener.
+async fn deliver_transcript(
+    ctx: &Context,
+    channel_id: ChannelId,
+    transcript_channel_id: Option<ChannelId>,
+    reason: CloseReason,
+) -> Result<(), serenity::Error> {
+    let ticket = tickets::find_by_channel(channel_id.get());
+    let (title, file_stem) = match &ticket {
+        Some(ticket) => (format!("Ticket #{}", ticket.id), format!("ticket-{}", ticket.id)),
+        None => (format!("Channel {}", channel_id), format!("channel-{}", channel_id)),
+    };
+    let mut transcript = transcript::fetch_transcript(ctx, channel_id, title.clone()).await?;
+    let notes = ticket.as_ref().map_or(&[][..], |ticket| &ticket.notes[..]);
+    let (opener_copy, staff_copy) = transcript.render_copies(notes);
+    let files = |copy: RenderedTranscript| {
+        vec![
+            CreateAttachment::bytes(copy.html.into_bytes(), format!("{}.html", file_stem)),
+            CreateAttachment::bytes(copy.markdown.into_bytes(), format!("{}.md", file_stem)),
+        ]
+    };
+
+    if let Some(log_channel) = transcript_channel_id {
+        let message = CreateMessage::new()
+            .content(format!("📁 Transcript of {}, {} ({} messages)", title, reason.label(), transcript.messages.len()))
+            .add_files(files(staff_copy));
+        if let Err(e) = log_channel.send_message(&ctx.http, message).await {
+            eprintln!("🛑 Failed to post transcript of {}: {:?}", title, e);
+        }
+    }
+    if let Some(ticket) = &ticket {
+        let message = CreateMessage::new()
+            .content(format!("📁 Your {} was {}. Here is a copy of the conversation.", title, reason.label()))
+            .add_files(files(opener_copy));
+        if let Err(e) = UserId::new(ticket.opener_id).direct_message(&ctx.http, message).await {
+            eprintln!("🛑 Failed to DM transcript of {} to {}: {:?}", title, ticket.opener_id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Closes a ticket the way `settings.mode` asks for. Channels without a
+/// ticket record are always deleted.
+pub async fn close_ticket(
+    ctx: &Context,
+    channel_id: ChannelId,
+    transcript_channel_id: Option<ChannelId>,
+    reason: CloseReason,
+    settings: &TicketCloseConfig,
+) -> Result<(), serenity::Error> {
+    // The history is gone once the channel is deleted, so a failed export aborts the close.
+    deliver_transcript(ctx, channel_id, transcript_channel_id, reason).await?;
+    let ticket = tickets::find_by_channel(channel_id.get());
+    match &ticket {
+        Some(ticket) if settings.mode == CloseMode::Archive => {
+            archive_ticket(ctx, ticket, reason, settings.archive_category_id).await?;
+        }
+        _ => delete_ticket(ctx, channel_id, reason).await?,
+    }
+    if let Some(ticket) = ticket
+        && let Err(e) = request_rating(ctx, &ticket).await
+    {
+        eprintln!("🛑 Failed to ask {} to rate ticket #{}: {:?}", ticket.opener_id, ticket.id, e);
+    }
+    Ok(())
+}
+
+/// DMs the opener of a closed ticket a row of 1 to 5 star buttons.
+async fn request_rating(ctx: &Context, ticket: &Ticket) -> Result<(), serenity::Error> {
+    let buttons = (1..=5)
+        .map(|stars| {
+            CreateButton::new(format!("{}:{}:{}", RATE_TICKET_BUTTON, ticket.id, stars))
+                .label(stars.to_string())
+                .emoji('⭐')
+                .style(ButtonStyle::Secondary)
+        })
+        .collect();
+    let message = CreateMessage::new()
+        .content(format!("⭐ How happy are you with the help you got in ticket #{}?", ticket.id))
+        .components(vec![CreateActionRow::Buttons(buttons)]);
+    UserId::new(ticket.opener_id).direct_message(&ctx.http, message).await?;
+    Ok(())
+}
+
+pub fn feedback_button(ticket_id: u32) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}:{}", FEEDBACK_BUTTON, ticket_id)).label("Leave feedback").emoji('📝').style(ButtonStyle::Primary),
+    ])
+}
+
+pub fn feedback_modal(ticket_id: u32) -> CreateModal {
+    let input = CreateInputText::new(InputTextStyle::Paragraph, "What went well, what could be better?", "feedback")
+        .required(false)
+        .max_length(MAX_ANSWER_LENGTH);
+    CreateModal::new(format!("{}:{}", FEEDBACK_MODAL, ticket_id), format!("📝 Ticket #{}", ticket_id))
+        .components(vec![CreateActionRow::InputText(input)])
+}
+
+/// Removes the channel for good and marks its ticket closed.
+pub async fn delete_ticket(ctx: &Context, channel_id: ChannelId, reason: CloseReason) -> Result<(), serenity::Error> {
     channel_id.delete(&ctx.http).await?;
+    match tickets::mark_closed(channel_id.get(), reason) {
+        Ok(Some(ticket)) => println!("✅ Ticket #{} {}", ticket.id, reason.label()),
+        Ok(None) => println!("ℹ️ Closed channel {} has no ticket record", channel_id),
+        Err(e) => eprintln!("🛑 Failed to record closing of ticket channel {}: {:?}", channel_id, e),
+    }
+    Ok(())
+}
+
+/// Discord answers "Unknown Channel" once a channel is gone.
+pub fn is_unknown_channel(error: &serenity::Error) -> bool {
+    const UNKNOWN_CHANNEL: isize = 10003;
+    matches!(error, serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) if response.error.code == UNKNOWN_CHANNEL)
+}
+
+/// Records a ticket whose channel was deleted outside the bot as closed.
+pub fn forget_deleted_channel(channel_id: ChannelId) {
+    match tickets::find_by_channel(channel_id.get()) {
+        Some(ticket) if ticket.status != TicketStatus::Closed => match tickets::mark_closed(channel_id.get(), CloseReason::ChannelDeleted) {
+            Ok(_) => println!("✅ Ticket #{} {}", ticket.id, CloseReason::ChannelDeleted.label()),
+            Err(e) => eprintln!("🛑 Failed to record deleted channel of ticket #{}: {:?}", ticket.id, e),
+        },
+        _ => {}
+    }
+}
+
+/// Posts the inactivity warning with a button that keeps the ticket open.
+pub async fn warn_inactive_ticket(ctx: &Context, ticket: &Ticket, grace_secs: u64) -> Result<Message, serenity::Error> {
+    let button = CreateActionRow::Buttons(vec![
+        CreateButton::new(KEEP_OPEN_BUTTON).label("Keep open").emoji('⏳').style(ButtonStyle::Success),
+    ]);
+    let message = CreateMessage::new()
+        .content(format!(
+            "⏳ <@{}> this ticket has been quiet for a while and will be closed <t:{}:R> unless someone replies.",
+            ticket.opener_id,
+            balance::current_unix_time() + grace_secs
+        ))
+        .components(vec![button]);
+    ChannelId::new(ticket.channel_id).send_message(&ctx.http, message).await
+}
+
+/// Soft-closes a ticket: every member overwrite except the bot's and the
+/// assignee's is removed, and the channel becomes `closed-N` in the archive.
+async fn archive_ticket(
+    ctx: &Context,
+    ticket: &Ticket,
+    reason: CloseReason,
+    archive_category_id: Option<u64>,
+) -> Result<(), serenity::Error> {
+    let channel_id = ChannelId::new(ticket.channel_id);
+    let bot_id = ctx.cache.current_user().id;
+    if let Some(channel) = channel_id.to_channel(&ctx.http).await?.guild() {
+        for overwrite in &channel.permission_overwrites {
+            if let PermissionOverwriteType::Member(user_id) = overwrite.kind
+                && user_id != bot_id
+                && Some(user_id.get()) != ticket.assigned_to
+            {
+                channel_id.delete_permission(&ctx.http, overwrite.kind).await?;
+            }
+        }
+    }
+    let mut edit = EditChannel::new().name(format!("closed-{}", ticket.id));
+    if let Some(category_id) = archive_category_id {
+        edit = edit.category(ChannelId::new(category_id));
+    }
+    channel_id.edit(&ctx.http, edit).await?;
+    tickets::mark_archived(ticket.channel_id, reason)?;
+    println!("✅ Ticket #{} archived, {}", ticket.id, reason.label());
+    Ok(())
+}
+
+/// Undoes `archive_ticket`: the opener gets access back and the channel
+/// returns to its original name and category.
+pub async fn reopen_ticket(ctx: &Context, ticket: &Ticket, ticket_type: Option<&TicketType>) -> Result<(), serenity::Error> {
+    let channel_id = ChannelId::new(ticket.channel_id);
+    channel_id.create_permission(&ctx.http, member_overwrite(UserId::new(ticket.opener_id))).await?;
+    let prefix = ticket_type.map_or("ticket", |ticket_type| ticket_type.channel_prefix.as_str());
+    let mut edit = EditChannel::new().name(format!("{}-{}", prefix, ticket.id));
+    if let Some(category_id) = ticket.category_id.or(ticket_type.map(|ticket_type| ticket_type.category_id)) {
+        edit = edit.category(ChannelId::new(category_id));
+    }
+    channel_id.edit(&ctx.http, edit).await?;
+    tickets::mark_reopened(ticket.channel_id)?;
+    println!("✅ Ticket #{} reopened", ticket.id);
+    Ok(())
+}
+
+/// Lets `user_id` read and write in the ticket channel.
+pub async fn add_ticket_member(ctx: &Context, channel_id: ChannelId, user_id: UserId) -> Result<(), serenity::Error> {
+    channel_id.create_permission(&ctx.http, member_overwrite(user_id)).await
+}
+
+pub async fn remove_ticket_member(ctx: &Context, channel_id: ChannelId, user_id: UserId) -> Result<(), serenity::Error> {
+    channel_id.delete_permission(&ctx.http, PermissionOverwriteType::Member(user_id)).await
+}
+
+/// Hands a ticket to `assignee`, or back to all moderators with `None`. While
+/// claimed only the assignee and the opener can write; the mod roles keep
+/// read access. The assignee is persisted with the ticket.
+pub async fn assign_ticket(
+    ctx: &Context,
+    ticket: &Ticket,
+    assignee: Option<UserId>,
+    mod_role_ids: &[RoleId],
+) -> Result<(), serenity::Error> {
+    let channel_id = ChannelId::new(ticket.channel_id);
+    for mod_role in mod_role_ids {
+        channel_id.create_permission(&ctx.http, staff_overwrite(*mod_role, assignee.is_none())).await?;
+    }
+    if let Some(previous) = ticket.assigned_to
+        && previous != ticket.opener_id
+        && assignee.map(UserId::get) != Some(previous)
+    {
+        channel_id.delete_permission(&ctx.http, PermissionOverwriteType::Member(UserId::new(previous))).await?;
+    }
+    if let Some(assignee) = assignee {
+        channel_id.create_permission(&ctx.http, member_overwrite(assignee)).await?;
+    }
+    let topic = match assignee {
+        Some(assignee) => format!("🙋 Claimed by <@{}>", assignee),
+        None => "📍 Waiting for a moderator".to_string(),
+    };
+    channel_id.edit(&ctx.http, EditChannel::new().topic(topic)).await?;
+    tickets::update(ticket.channel_id, |ticket| ticket.assigned_to = assignee.map(UserId::get))?;
     Ok(())
 }
 
@@ -83,28 +468,219 @@ pub async fn clear_global_commands(ctx: &Context) {
     }
 }
 
-pub async fn register_commands(ctx: &Context, guild_id: GuildId) {
+fn eco_amount_subcommand(name: &str, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+        .add_sub_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "Target member")
+                .required(true)
+        )
+        .add_sub_option(
+            CreateCommandOption::new(CommandOptionType::String, "amount", "Amount, e.g. 12.50")
+                .required(true)
+        )
+        .add_sub_option(
+            CreateCommandOption::new(CommandOptionType::String, "reason", "Why this change is made")
+                .required(true)
+        )
+}
+
+pub async fn register_commands(ctx: &Context, guild_id: GuildId, ticket_types: &[TicketType]) {
+    let ticket_type_option = ticket_types.iter().take(25).fold(
+        CreateCommandOption::new(CommandOptionType::String, "type", "Kind of ticket to open"),
+        |option, ticket_type| option.add_string_choice(ticket_type.label(), &ticket_type.name),
+    );
     let commands = vec![
-        CreateCommand::new("ticket").description("📍 Creates a new ticket"),
+        CreateCommand::new("ticket").description("📍 Open and manage tickets")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "open", "Create a new ticket")
+                    .add_sub_option(ticket_type_option)
+            )
+            .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "claim", "Take ownership of this ticket"))
+            .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "unclaim", "Release this ticket to all moderators"))
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "assign", "Hand this ticket to another moderator")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::User, "moderator", "Moderator to take over")
+                            .required(true)
+                    )
+            )
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "add", "Give a member access to this ticket")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::User, "user", "Member to add")
+                            .required(true)
+                    )
+            )
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "Take a member's access to this ticket away")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::User, "user", "Member to remove")
+                            .required(true)
+                    )
+            )
+            .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "reopen", "Reopen this archived ticket"))
+            .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "delete", "Delete this ticket's channel for good"))
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "note", "Add a private note for moderators")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "text", "The note, never shown to the opener")
+                            .required(true)
+                            .max_length(1000)
+                    )
+            )
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "tag", "Tag this ticket for later search")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "tag", "e.g. refund, bug, duplicate")
+                            .required(true)
+                            .max_length(32)
+                    )
+                    .add_sub_option(CreateCommandOption::new(CommandOptionType::Boolean, "remove", "Remove the tag instead"))
+            )
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "search", "Find closed tickets by tag")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "tag", "Tag to look for")
+                            .required(true)
+                    )
+            )
+            .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "ratings", "Show average ratings per moderator"))
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "stats", "Show support queue statistics")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "period", "Tickets opened in this period")
+                            .add_string_choice("Last 24 hours", "day")
+                            .add_string_choice("Last 7 days", "week")
+                            .add_string_choice("Last 30 days", "month")
+                            .add_string_choice("All time", "all")
+                    )
+            ),
         CreateCommand::new("ticketclose").description("📁 Closes the current ticket"),
+        CreateCommand::new("ticketpanel").description("📌 Posts the ticket panel in this channel"),
         CreateCommand::new("pact").description("🪙 Enter the Twilight Financial Pact")
             .add_option(
-                CreateCommandOption::new(CommandOptionType::Number, "stake", "Stake amount")
+                CreateCommandOption::new(CommandOptionType::String, "stake", "Stake amount, e.g. 12.50")
                     .required(true)
             ),
+        CreateCommand::new("fairness").description("🎲 Provably fair pact seeds")
+            .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "show", "Show your seed hash, client seed and nonce"))
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "seed", "Set your client seed")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "client_seed", "Any text up to 64 characters")
+                            .required(true)
+                    )
+            )
+            .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "rotate", "Reveal your server seed and get a new one"))
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "verify", "Recompute a past pact roll")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "server_seed", "Revealed server seed")
+                            .required(true)
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "client_seed", "Client seed used for the roll")
+                            .required(true)
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::Integer, "nonce", "Nonce of the roll")
+                            .required(true)
+                            .min_int_value(0)
+                    )
+            ),
+        CreateCommand::new("daily").description("🎁 Claim your daily reward"),
         CreateCommand::new("bind").description("🖇️ Bind id to database"),
         CreateCommand::new("balance").description("💼 Show your current balance"),
+        CreateCommand::new("history").description("📜 Show your recent transactions")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::Integer, "page", "Page number")
+                    .min_int_value(1)
+            ),
+        CreateCommand::new("leaderboard").description("🏆 Show the richest members")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::Integer, "limit", "Number of members to show")
+                    .min_int_value(1)
+                    .max_int_value(25)
+            ),
         CreateCommand::new("pay").description("💸 Transfer coins to another user")
             .add_option(
                 CreateCommandOption::new(CommandOptionType::User, "recipient", "User to pay")
                     .required(true)
             )
             .add_option(
-                CreateCommandOption::new(CommandOptionType::Number, "amount", "Amount to transfer")
+                CreateCommandOption::new(CommandOptionType::String, "amount", "Amount to transfer, e.g. 12.50")
                     .required(true)
             ),
+        CreateCommand::new("apikey").description("🔑 Manage HTTP API keys")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "create", "Create a key; the secret is sent to you once")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "name", "What the key is for")
+                            .required(true)
+                            .max_length(64)
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "scopes", "e.g. read:balance, read:tickets, write:economy")
+                            .required(true)
+                    )
+            )
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "revoke", "Revoke a key")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "id", "Key id from /apikey list")
+                            .required(true)
+                    )
+            )
+            .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "list", "List active keys")),
+        CreateCommand::new("eco").description("🛡️ Moderator economy tools")
+            .add_option(eco_amount_subcommand("grant", "Add coins to a member"))
+            .add_option(eco_amount_subcommand("revoke", "Remove coins from a member"))
+            .add_option(eco_amount_subcommand("set", "Set a member's balance"))
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::SubCommand, "freeze", "Freeze or unfreeze a member's account")
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::User, "user", "Target member")
+                            .required(true)
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::Boolean, "frozen", "Whether the account is frozen")
+                            .required(true)
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(CommandOptionType::String, "reason", "Why this change is made")
+                            .required(true)
+                    )
+            ),
     ];
     if let Err(e) = guild_id.set_commands(&ctx.http, commands).await {
         eprintln!("🛑 Failed to register commands: {}", e);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_intake_answers() {
+        let ticket_type: TicketType = serde_json::from_value(json!({
+            "name": "appeal",
+            "category_id": 1,
+            "questions": [{ "label": "Which ban?" }, { "label": "Why lift it?", "style": "paragraph" }],
+        }))
+        .unwrap();
+        let input = |custom_id: &str, value: &str| json!({ "type": 4, "custom_id": custom_id, "value": value });
+        let rows: Vec<ActionRow> = serde_json::from_value(json!([
+            { "type": 1, "components": [input("question_1", "It was a mistake")] },
+            { "type": 1, "components": [input("question_0", "#123")] },
+            { "type": 1, "components": [input("question_7", "unknown question")] },
+            { "type": 1, "components": [input("other", "not a question")] },
+        ]))
+        .unwrap();
+
+        let answers = intake_answers(&ticket_type, &rows);
+        let pairs: Vec<(&str, &str)> = answers.iter().map(|answer| (answer.question.as_str(), answer.answer.as_str())).collect();
+        assert_eq!(pairs, vec![("Why lift it?", "It was a mistake"), ("Which ban?", "#123")]);
+    }
+}
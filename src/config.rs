@@ -1,24 +1,511 @@
 use serde::{Serialize, Deserialize};
+use std::env;
 use std::fs;
 use std::path::Path;
 use std::io;
 use serenity::model::prelude::*;
 
+use crate::money::{self, Money};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Json,
+    Sqlite,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: StorageBackend,
+    #[serde(default = "default_sqlite_path")]
+    pub sqlite_path: String,
+}
+
+fn default_sqlite_path() -> String {
+    "./data/arcwarden.db".to_string()
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::default(),
+            sqlite_path: default_sqlite_path(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PactConfig {
+    /// Probability in `[0, 1]` that a pact pays out.
+    pub win_chance: f64,
+    pub min_percent: u32,
+    pub max_percent: u32,
+    pub cooldown_secs: u64,
+    /// Roll pacts with the commit-reveal scheme in `fairness.rs`.
+    pub provably_fair: bool,
+}
+
+impl PactConfig {
+    fn validate(&self) -> io::Result<()> {
+        if !(0.0..=1.0).contains(&self.win_chance) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "pact.win_chance must be between 0 and 1"));
+        }
+        if self.min_percent > self.max_percent {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "pact.min_percent must not exceed pact.max_percent"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for PactConfig {
+    fn default() -> Self {
+        PactConfig {
+            win_chance: 0.45,
+            min_percent: 7,
+            max_percent: 32,
+            cooldown_secs: 86400,
+            provably_fair: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DailyConfig {
+    /// Paid on every claim, written as whole coins, e.g. `10` or `"12.50"`.
+    #[serde(with = "money::coins")]
+    pub base_reward: Money,
+    /// Added per consecutive day after the first.
+    #[serde(with = "money::coins")]
+    pub streak_bonus: Money,
+    /// Streak length after which the bonus stops growing.
+    pub max_streak: u32,
+    pub cooldown_secs: u64,
+    /// A claim later than this after the previous one starts a new streak.
+    pub streak_reset_secs: u64,
+}
+
+impl Default for DailyConfig {
+    fn default() -> Self {
+        DailyConfig {
+            base_reward: Money::from_coins(10),
+            streak_bonus: Money::from_coins(2),
+            max_streak: 7,
+            cooldown_secs: 86400,
+            streak_reset_secs: 2 * 86400,
+        }
+    }
+}
+
+fn default_channel_prefix() -> String {
+    "ticket".to_string()
+}
+
+fn default_welcome_message() -> String {
+    "thanks for reaching out, a moderator will be with you shortly.".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QuestionStyle {
+    #[default]
+    Short,
+    Paragraph,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+/// One field of the form shown when a ticket is opened.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IntakeQuestion {
+    /// Discord shows at most 45 characters.
+    pub label: String,
+    #[serde(default)]
+    pub style: QuestionStyle,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    #[serde(default)]
+    pub placeholder: Option<String>,
+}
+
+/// A kind of ticket users can open, e.g. "support" or "appeal".
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TicketType {
+    /// Identifier used in commands and buttons.
+    pub name: String,
+    /// Shown on the panel button and in `/ticket open`; defaults to `name`.
+    #[serde(default)]
+    pub label: Option<String>,
+    pub category_id: u64,
+    /// Roles that may see and manage these tickets; empty means `mod_roles`.
+    #[serde(default)]
+    pub mod_roles: Vec<u64>,
+    #[serde(default = "default_channel_prefix")]
+    pub channel_prefix: String,
+    #[serde(default = "default_welcome_message")]
+    pub welcome_message: String,
+    /// Asked in a modal before the channel is created; Discord allows five.
+    #[serde(default)]
+    pub questions: Vec<IntakeQuestion>,
+}
+
+/// Discord rejects modal titles and input labels longer than this.
+const MAX_MODAL_TEXT_LENGTH: usize = 45;
+/// Discord rejects input placeholders longer than this.
+const MAX_PLACEHOLDER_LENGTH: usize = 100;
+/// A modal holds at most five inputs.
+const MAX_QUESTIONS: usize = 5;
+
+impl TicketType {
+    pub fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn modal_title(&self) -> String {
+        format!("📍 {}", self.label())
+    }
+
+    /// Catches intake forms Discord would refuse to show.
+    fn validate(&self) -> io::Result<()> {
+        let invalid = |message: String| Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("ticket type {}: {}", self.name, message),
+        ));
+        let longer_than = |text: &str, limit: usize| text.chars().count() > limit;
+        if longer_than(&self.modal_title(), MAX_MODAL_TEXT_LENGTH) {
+            return invalid(format!("label must fit a {}-character modal title", MAX_MODAL_TEXT_LENGTH));
+        }
+        if self.questions.len() > MAX_QUESTIONS {
+            return invalid(format!("at most {} questions are allowed, got {}", MAX_QUESTIONS, self.questions.len()));
+        }
+        for question in &self.questions {
+            if longer_than(&question.label, MAX_MODAL_TEXT_LENGTH) {
+                return invalid(format!("question {:?} is longer than {} characters", question.label, MAX_MODAL_TEXT_LENGTH));
+            }
+            if let Some(placeholder) = &question.placeholder
+                && longer_than(placeholder, MAX_PLACEHOLDER_LENGTH)
+            {
+                return invalid(format!("placeholder of {:?} is longer than {} characters", question.label, MAX_PLACEHOLDER_LENGTH));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct InactivityConfig {
+    pub enabled: bool,
+    /// Silence after which a ticket gets a warning with a "Keep open" button.
+    pub idle_secs: u64,
+    /// Further silence after the warning before the ticket is closed.
+    pub grace_secs: u64,
+    /// How often open tickets are checked.
+    pub check_interval_secs: u64,
+}
+
+impl Default for InactivityConfig {
+    fn default() -> Self {
+        InactivityConfig {
+            enabled: false,
+            idle_secs: 3 * 86400,
+            grace_secs: 86400,
+            check_interval_secs: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TicketLimitsConfig {
+    /// Open tickets a member may have at once; `0` disables the limit.
+    pub max_open_per_user: usize,
+    /// Minimum time between two tickets opened by the same member.
+    pub creation_cooldown_secs: u64,
+}
+
+impl Default for TicketLimitsConfig {
+    fn default() -> Self {
+        TicketLimitsConfig {
+            max_open_per_user: 1,
+            creation_cooldown_secs: 300,
+        }
+    }
+}
+
+/// What closing a ticket does with its channel.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CloseMode {
+    /// Delete the channel.
+    #[default]
+    Delete,
+    /// Lock the opener out, rename the channel `closed-N` and keep it for `/ticket reopen`.
+    Archive,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TicketCloseConfig {
+    pub mode: CloseMode,
+    /// Category archived tickets are moved to; they stay in place without one.
+    pub archive_category_id: Option<u64>,
+}
+
+/// HTTP API listener. Every field can be overridden by the `API_*` env vars.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+    /// Worker threads; actix picks one per CPU core when unset.
+    pub workers: Option<usize>,
+    /// PEM certificate chain and private key. Both must be set to serve HTTPS.
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            enabled: true,
+            bind_address: "127.0.0.1".to_string(),
+            port: 8080,
+            workers: None,
+            tls_cert_path: None,
+            tls_key_path: None,
+        }
+    }
+}
+
+fn env_override<T: std::str::FromStr>(name: &str, field: &mut T) -> io::Result<()>
+where
+    T::Err: std::fmt::Display,
+{
+    if let Ok(value) = env::var(name) {
+        *field = value.trim().parse().map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{}={:?}: {}", name, value, err))
+        })?;
+    }
+    Ok(())
+}
+
+impl ApiConfig {
+    /// Applies `API_ENABLED`, `API_BIND_ADDRESS`, `API_PORT`, `API_WORKERS`,
+    /// `API_TLS_CERT` and `API_TLS_KEY` on top of the file values.
+    fn apply_env(&mut self) -> io::Result<()> {
+        env_override("API_ENABLED", &mut self.enabled)?;
+        env_override("API_BIND_ADDRESS", &mut self.bind_address)?;
+        env_override("API_PORT", &mut self.port)?;
+        if env::var("API_WORKERS").is_ok() {
+            let mut workers = 0;
+            env_override("API_WORKERS", &mut workers)?;
+            self.workers = Some(workers);
+        }
+        if let Ok(path) = env::var("API_TLS_CERT") {
+            self.tls_cert_path = Some(path);
+        }
+        if let Ok(path) = env::var("API_TLS_KEY") {
+            self.tls_key_path = Some(path);
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        if self.workers == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "api.workers must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+fn default_currency_decimals() -> u32 {
+    2
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub ticket_category_id: Vec<u64>,
     pub allowed_channel_id: u64,
     pub allowed_ticket_cat_id: u64,
     pub mod_roles: Vec<u64>,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default = "default_currency_decimals")]
+    pub currency_decimals: u32,
+    #[serde(default)]
+    pub pact: PactConfig,
+    #[serde(default)]
+    pub daily: DailyConfig,
+    /// Channel that receives a transcript of every closed ticket.
+    #[serde(default)]
+    pub transcript_channel_id: Option<u64>,
+    #[serde(default)]
+    pub ticket_types: Vec<TicketType>,
+    #[serde(default)]
+    pub inactivity: InactivityConfig,
+    #[serde(default)]
+    pub ticket_limits: TicketLimitsConfig,
+    #[serde(default)]
+    pub ticket_close: TicketCloseConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
 }
 
 pub fn load_config<P: AsRef<Path>>(path: P) -> io::Result<Config> {
     let config_str = fs::read_to_string(path)?;
-    let config: Config = serde_json::from_str(&config_str)
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let value: serde_json::Value = serde_json::from_str(&config_str).map_err(io::Error::other)?;
+    // Amounts in the config are written in whole coins, so the scale must be known first.
+    let decimals = value.get("currency_decimals").and_then(|d| d.as_u64()).unwrap_or(2);
+    if decimals > u64::from(money::MAX_DECIMALS) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("currency_decimals must be at most {}, got {}", money::MAX_DECIMALS, decimals),
+        ));
+    }
+    money::set_decimals(decimals as u32);
+    let mut config: Config = serde_json::from_value(value).map_err(io::Error::other)?;
+    config.pact.validate()?;
+    for ticket_type in &config.ticket_types {
+        ticket_type.validate()?;
+    }
+    config.api.apply_env()?;
+    config.api.validate()?;
     Ok(config)
 }
 
 pub fn get_mod_roles(config: &Config) -> Vec<RoleId> {
     config.mod_roles.iter().map(|&id| RoleId::new(id)).collect()
-}
\ No newline at end of file
+}
+
+/// The configured ticket types. Configs without `ticket_types` get a single
+/// "support" type in the first entry of `ticket_category_id`.
+pub fn get_ticket_types(config: &Config) -> Vec<TicketType> {
+    if !config.ticket_types.is_empty() {
+        return config.ticket_types.clone();
+    }
+    config.ticket_category_id.first()
+        .map(|&category_id| TicketType {
+            name: "support".to_string(),
+            label: None,
+            category_id,
+            mod_roles: Vec::new(),
+            channel_prefix: default_channel_prefix(),
+            welcome_message: default_welcome_message(),
+            questions: Vec::new(),
+        })
+        .into_iter()
+        .collect()
+}
+
+/// Looks up a ticket type by name; `None` picks the first one.
+pub fn find_ticket_type(config: &Config, name: Option<&str>) -> Option<TicketType> {
+    let ticket_types = get_ticket_types(config);
+    match name {
+        Some(name) => ticket_types.into_iter().find(|ticket_type| ticket_type.name == name),
+        None => ticket_types.into_iter().next(),
+    }
+}
+
+/// Roles that manage tickets of the named type, falling back to `mod_roles`
+/// for untyped tickets and types that were removed from the config.
+pub fn get_ticket_roles(config: &Config, type_name: Option<&str>) -> Vec<RoleId> {
+    match type_name.and_then(|name| find_ticket_type(config, Some(name))) {
+        Some(ticket_type) if !ticket_type.mod_roles.is_empty() => {
+            ticket_type.mod_roles.iter().map(|&id| RoleId::new(id)).collect()
+        }
+        _ => get_mod_roles(config),
+    }
+}
+
+/// Every category ticket channels may live in.
+pub fn get_ticket_categories(config: &Config) -> Vec<u64> {
+    let mut categories: Vec<u64> = get_ticket_types(config).iter().map(|ticket_type| ticket_type.category_id).collect();
+    categories.extend(&config.ticket_category_id);
+    categories.push(config.allowed_ticket_cat_id);
+    categories
+}
+
+/// Categories a new ticket of this type may go into, in order: its own
+/// category, then the entries of `ticket_category_id` that follow it.
+pub fn get_overflow_categories(config: &Config, ticket_type: &TicketType) -> Vec<u64> {
+    let following = match config.ticket_category_id.iter().position(|&id| id == ticket_type.category_id) {
+        Some(index) => &config.ticket_category_id[index + 1..],
+        None => &config.ticket_category_id[..],
+    };
+    let mut categories = vec![ticket_type.category_id];
+    for &category_id in following {
+        if !categories.contains(&category_id) {
+            categories.push(category_id);
+        }
+    }
+    categories
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(ticket_category_id: &[u64]) -> Config {
+        serde_json::from_value(json!({
+            "ticket_category_id": ticket_category_id,
+            "allowed_channel_id": 1,
+            "allowed_ticket_cat_id": 2,
+            "mod_roles": [],
+        }))
+        .unwrap()
+    }
+
+    fn ticket_type(category_id: u64) -> TicketType {
+        serde_json::from_value(json!({ "name": "support", "category_id": category_id })).unwrap()
+    }
+
+    #[test]
+    fn test_intake_limits() {
+        let mut ticket_type = ticket_type(1);
+        ticket_type.label = Some("x".repeat(43));
+        assert!(ticket_type.validate().is_ok());
+        // The title is checked even without questions.
+        ticket_type.label = Some("x".repeat(44));
+        assert!(ticket_type.validate().is_err());
+        ticket_type.label = None;
+
+        ticket_type.questions = serde_json::from_value(json!([{ "label": "y".repeat(45), "placeholder": "z".repeat(100) }])).unwrap();
+        assert!(ticket_type.validate().is_ok());
+        ticket_type.questions[0].placeholder = Some("z".repeat(101));
+        assert!(ticket_type.validate().is_err());
+        ticket_type.questions[0].placeholder = None;
+        ticket_type.questions[0].label.push('y');
+        assert!(ticket_type.validate().is_err());
+
+        ticket_type.questions = vec![ticket_type.questions[0].clone(); 6];
+        ticket_type.questions.iter_mut().for_each(|question| question.label.truncate(10));
+        assert!(ticket_type.validate().is_err());
+        ticket_type.questions.pop();
+        assert!(ticket_type.validate().is_ok());
+    }
+
+    #[test]
+    fn test_overflow_categories() {
+        let listed = config(&[10, 20, 30]);
+        assert_eq!(get_overflow_categories(&listed, &ticket_type(20)), vec![20, 30]);
+        assert_eq!(get_overflow_categories(&listed, &ticket_type(30)), vec![30]);
+        // A type outside the list may spill into any listed category.
+        assert_eq!(get_overflow_categories(&listed, &ticket_type(5)), vec![5, 10, 20, 30]);
+        assert_eq!(get_overflow_categories(&config(&[10, 10, 20]), &ticket_type(5)), vec![5, 10, 20]);
+    }
+
+    #[test]
+    fn test_api_workers() {
+        assert!(ApiConfig::default().validate().is_ok());
+        assert!(ApiConfig { workers: Some(1), ..ApiConfig::default() }.validate().is_ok());
+        assert!(ApiConfig { workers: Some(0), ..ApiConfig::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::PactConfig;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Outcome of one pact before it is applied to a balance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PactRoll {
+    /// Uniform value in `[0, 1)`; the pact wins when it is below `win_chance`.
+    pub roll: f64,
+    pub win: bool,
+    pub percent: u32,
+}
+
+/// Everything needed to recompute a provably fair roll once its server
+/// seed has been revealed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PactProof {
+    pub server_seed_hash: String,
+    pub client_seed: String,
+    pub nonce: u64,
+    pub win_chance: f64,
+    pub min_percent: u32,
+    pub max_percent: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevealedSeed {
+    pub server_seed: String,
+    pub server_seed_hash: String,
+    /// Number of pacts rolled with this seed; their nonces are `0..rolls`.
+    pub rolls: u64,
+}
+
+/// Per-account commit-reveal state. Only `server_seed_hash` is shown until
+/// the seed is rotated, after which the old seed is published.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FairnessState {
+    pub server_seed: String,
+    pub server_seed_hash: String,
+    pub client_seed: String,
+    pub nonce: u64,
+    #[serde(default)]
+    pub revealed: Vec<RevealedSeed>,
+}
+
+impl FairnessState {
+    pub fn new() -> Self {
+        let server_seed = generate_seed(32);
+        FairnessState {
+            server_seed_hash: hash_seed(&server_seed),
+            server_seed,
+            client_seed: generate_seed(8),
+            nonce: 0,
+            revealed: Vec::new(),
+        }
+    }
+
+    /// Publishes the current server seed and commits to a fresh one.
+    pub fn rotate(&mut self) -> RevealedSeed {
+        let revealed = RevealedSeed {
+            server_seed: self.server_seed.clone(),
+            server_seed_hash: self.server_seed_hash.clone(),
+            rolls: self.nonce,
+        };
+        self.server_seed = generate_seed(32);
+        self.server_seed_hash = hash_seed(&self.server_seed);
+        self.nonce = 0;
+        self.revealed.push(revealed.clone());
+        revealed
+    }
+}
+
+pub fn generate_seed(bytes: usize) -> String {
+    let mut rng = rand::rng();
+    let seed: Vec<u8> = (0..bytes).map(|_| rng.random()).collect();
+    hex::encode(seed)
+}
+
+pub fn hash_seed(server_seed: &str) -> String {
+    hex::encode(Sha256::digest(server_seed.as_bytes()))
+}
+
+/// Deterministic roll from `HMAC-SHA256(server_seed, "client_seed:nonce")`.
+/// The first 4 bytes pick the win roll, the next 4 the percentage in
+/// `min_percent..=max_percent`.
+pub fn fair_roll(server_seed: &str, client_seed: &str, nonce: u64, win_chance: f64, min_percent: u32, max_percent: u32) -> PactRoll {
+    let mut mac = HmacSha256::new_from_slice(server_seed.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}:{}", client_seed, nonce).as_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let roll_bits = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+    let percent_bits = u32::from_be_bytes([digest[4], digest[5], digest[6], digest[7]]);
+    let roll = roll_bits as f64 / (u32::MAX as f64 + 1.0);
+    let span = u64::from(max_percent.saturating_sub(min_percent)) + 1;
+    PactRoll {
+        roll,
+        win: roll < win_chance,
+        percent: min_percent + (u64::from(percent_bits) % span) as u32,
+    }
+}
+
+/// Rolls a pact with the configured odds, advancing `state` when the
+/// provably fair mode is used.
+pub fn roll_pact(settings: &PactConfig, state: Option<&mut FairnessState>) -> (PactRoll, Option<PactProof>) {
+    let (min_percent, max_percent) = (settings.min_percent, settings.max_percent);
+    match state {
+        Some(state) => {
+            let roll = fair_roll(&state.server_seed, &state.client_seed, state.nonce, settings.win_chance, min_percent, max_percent);
+            let proof = PactProof {
+                server_seed_hash: state.server_seed_hash.clone(),
+                client_seed: state.client_seed.clone(),
+                nonce: state.nonce,
+                win_chance: settings.win_chance,
+                min_percent,
+                max_percent,
+            };
+            state.nonce += 1;
+            (roll, Some(proof))
+        }
+        None => {
+            let mut rng = rand::rng();
+            let roll: f64 = rng.random_range(0.0..1.0);
+            let pact_roll = PactRoll {
+                roll,
+                win: roll < settings.win_chance,
+                percent: rng.random_range(min_percent..=max_percent),
+            };
+            (pact_roll, None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fair_roll_is_deterministic() {
+        let first = fair_roll("server", "client", 3, 0.45, 7, 32);
+        let second = fair_roll("server", "client", 3, 0.45, 7, 32);
+        assert_eq!(first, second);
+        assert!((7..=32).contains(&first.percent));
+        assert_ne!(first, fair_roll("server", "client", 4, 0.45, 7, 32));
+        // The widest range must not overflow.
+        fair_roll("server", "client", 3, 0.45, 0, u32::MAX);
+    }
+
+    #[test]
+    fn test_rotate_reveals_committed_seed() {
+        let mut state = FairnessState::new();
+        let committed_hash = state.server_seed_hash.clone();
+        state.nonce = 5;
+        let revealed = state.rotate();
+        assert_eq!(hash_seed(&revealed.server_seed), committed_hash);
+        assert_eq!(revealed.rolls, 5);
+        assert_eq!(state.nonce, 0);
+        assert_ne!(state.server_seed_hash, committed_hash);
+    }
+}
@@ -1,16 +1,38 @@
 use serenity::prelude::*;
 use serenity::model::prelude::*;
-use serenity::all::CommandDataOptionValue;
+use serenity::all::{CommandDataOption, CommandDataOptionValue};
+use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage, EditInteractionResponse};
 use serenity::model::id::{ChannelId};
 
+use crate::api_keys;
 use crate::config;
+use crate::config::{CloseMode, TicketCloseConfig};
 use crate::commands;
 use crate::balance;
+use crate::balance::AdminAction;
+use crate::money::Money;
+use crate::tickets::{self, CloseReason, StatsPeriod, Ticket, TicketStatus};
+use crate::inactivity;
 
 pub struct Handler {
     pub config: config::Config,
 }
 
+fn holds_any_role(member_roles: &[RoleId], roles: &[RoleId]) -> bool {
+    member_roles.iter().any(|role| roles.contains(role))
+}
+
+/// Whether the member holds one of the configured moderator roles.
+pub fn is_moderator(config: &config::Config, member: &Member) -> bool {
+    holds_any_role(&member.roles, &config::get_mod_roles(config))
+}
+
+/// Whether the member may manage tickets of `ticket_type`, whose own roles
+/// replace `mod_roles` when it has any.
+pub fn is_ticket_moderator(config: &config::Config, member: &Member, ticket_type: Option<&str>) -> bool {
+    holds_any_role(&member.roles, &config::get_ticket_roles(config, ticket_type))
+}
+
 #[serenity::async_trait]
 impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, ready: Ready) {
@@ -22,11 +44,53 @@ impl EventHandler for Handler {
         {
             commands::clear_guild_commands(&ctx, guild_id).await;
             commands::clear_global_commands(&ctx).await;
-            commands::register_commands(&ctx, guild_id).await;
+            commands::register_commands(&ctx, guild_id, &config::get_ticket_types(&self.config)).await;
+        }
+        inactivity::spawn(
+            ctx,
+            self.config.inactivity.clone(),
+            self.config.transcript_channel_id.map(ChannelId::new),
+            self.config.ticket_close.clone(),
+        );
+    }
+
+    async fn message(&self, _ctx: Context, message: Message) {
+        if message.author.bot || message.guild_id.is_none() {
+            return;
         }
+        let ticket = match tickets::find_by_channel(message.channel_id.get()) {
+            Some(ticket) if ticket.status == TicketStatus::Open => ticket,
+            _ => return,
+        };
+        if let Err(e) = tickets::record_activity(ticket.channel_id) {
+            eprintln!("🛑 Failed to record ticket activity in {}: {:?}", message.channel_id, e);
+        }
+        if ticket.first_response_at.is_none() && message.author.id.get() != ticket.opener_id {
+            let mod_roles = config::get_ticket_roles(&self.config, ticket.ticket_type.as_deref());
+            let is_staff = message.member.as_ref()
+                .is_some_and(|member| holds_any_role(&member.roles, &mod_roles));
+            if is_staff
+                && let Err(e) = tickets::record_first_response(ticket.channel_id, message.author.id.get())
+            {
+                eprintln!("🛑 Failed to record first response in ticket #{}: {:?}", ticket.id, e);
+            }
+        }
+    }
+
+    /// A ticket channel deleted by hand no longer counts as an open ticket.
+    async fn channel_delete(&self, _ctx: Context, channel: GuildChannel, _messages: Option<Vec<Message>>) {
+        commands::forget_deleted_channel(channel.id);
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Component(component) = interaction {
+            self.handle_component(&ctx, component).await;
+            return;
+        }
+        if let Interaction::Modal(modal) = interaction {
+            self.handle_modal(&ctx, modal).await;
+            return;
+        }
         if let Interaction::Command(command) = interaction {
             let Some(guild_id) = command.guild_id else {
                 if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
@@ -37,18 +101,87 @@ impl EventHandler for Handler {
 
             match command.data.name.as_str() {
                 "ticket" => {
+                    let Some(subcommand) = command.data.options.first() else {
+                        return;
+                    };
+                    let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+                        return;
+                    };
+                    // These look across all tickets rather than the current channel.
+                    if matches!(subcommand.name.as_str(), "stats" | "ratings" | "search") {
+                        let is_mod = command.member.as_deref().is_some_and(|member| is_moderator(&self.config, member));
+                        let option = |name: &str| options.iter().find(|opt| opt.name == name).and_then(|opt| opt.value.as_str());
+                        let result = if !is_mod {
+                            "🛑 You do not have permission to view ticket records.".to_string()
+                        } else {
+                            match subcommand.name.as_str() {
+                                "ratings" => tickets::rating_summary(),
+                                "search" => tickets::search_summary(option("tag").unwrap_or_default()),
+                                _ => {
+                                    let period = option("period").and_then(StatsPeriod::parse).unwrap_or(StatsPeriod::Week);
+                                    tickets::stats_summary(period)
+                                }
+                            }
+                        };
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, result).await;
+                        }
+                        return;
+                    }
+                    if subcommand.name != "open" {
+                        let result = self.manage_ticket(&ctx, &command, &subcommand.name, options).await;
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, result).await;
+                        }
+                        return;
+                    }
                     if command.channel_id != ChannelId::new(self.config.allowed_channel_id) {
                         if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
                             let _ = dm_channel.say(&ctx.http, "🛑 This command can only be used in the designated channel.").await;
                         }
                         return;
                     }
+                    let type_name = options.iter()
+                        .find(|opt| opt.name == "type")
+                        .and_then(|opt| opt.value.as_str());
+                    let Some(ticket_type) = config::find_ticket_type(&self.config, type_name) else {
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, "🛑 Unknown ticket type.").await;
+                        }
+                        return;
+                    };
+                    if !ticket_type.questions.is_empty() {
+                        // The channel is created once the intake form is submitted,
+                        // where the limits are checked again.
+                        if let Some(refusal) = tickets::open_refusal(command.user.id.get(), &self.config.ticket_limits) {
+                            if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                                let _ = dm_channel.say(&ctx.http, refusal).await;
+                            }
+                            return;
+                        }
+                        let modal = CreateInteractionResponse::Modal(commands::intake_modal(&ticket_type));
+                        if let Err(e) = command.create_response(&ctx.http, modal).await {
+                            eprintln!("🛑 Failed to show intake form: {:?}", e);
+                        }
+                        return;
+                    }
+                    let ticket_id = match tickets::reserve_id(command.user.id.get(), &self.config.ticket_limits) {
+                        Ok(ticket_id) => ticket_id,
+                        Err(refusal) => {
+                            if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                                let _ = dm_channel.say(&ctx.http, refusal).await;
+                            }
+                            return;
+                        }
+                    };
                     match commands::create_ticket_channel(
                         &ctx,
                         guild_id,
                         command.user.id,
-                        ChannelId::new(self.config.ticket_category_id[0]),
-                        &config::get_mod_roles(&self.config)
+                        ticket_id,
+                        &ticket_type,
+                        &self.config,
+                        Vec::new()
                     ).await {
                         Ok(channel_id) => {
                             if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
@@ -64,9 +197,10 @@ impl EventHandler for Handler {
                     }
                 },
                 "ticketclose" => {
-                    let mod_roles = config::get_mod_roles(&self.config);
-                    if let Some(member) = command.member.clone() {
-                        if !member.roles.iter().any(|role| mod_roles.contains(role)) {
+                    let ticket = tickets::find_by_channel(command.channel_id.get());
+                    let ticket_type = ticket.as_ref().and_then(|ticket| ticket.ticket_type.clone());
+                    if let Some(member) = command.member.as_deref() {
+                        if !is_ticket_moderator(&self.config, member, ticket_type.as_deref()) {
                             if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
                                 let _ = dm_channel.say(&ctx.http, "🛑 You do not have permission to close this ticket.").await;
                             }
@@ -78,6 +212,13 @@ impl EventHandler for Handler {
                         }
                         return;
                     }
+                    // Like the Close button; an archived ticket is removed with `/ticket delete`.
+                    if let Some(ticket) = ticket.filter(|ticket| ticket.status != TicketStatus::Open) {
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, format!("🛑 Ticket #{} is not open. Use /ticket delete to remove it.", ticket.id)).await;
+                        }
+                        return;
+                    }
                     let channel = command.channel_id.to_channel(&ctx.http).await.unwrap();
                     if let Some(guild_channel) = channel.guild() {
                         // Проверка, что канал не является запрещённым (например, канал настроек)
@@ -89,7 +230,7 @@ impl EventHandler for Handler {
                             return;
                         }
                         if let Some(parent) = guild_channel.parent_id {
-                            if u64::from(parent) != self.config.allowed_ticket_cat_id {
+                            if !config::get_ticket_categories(&self.config).contains(&u64::from(parent)) {
                                 if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
                                     let _ = dm_channel.say(&ctx.http, "🛑 This channel is not in the allowed ticket category.").await;
                                 }
@@ -102,7 +243,7 @@ impl EventHandler for Handler {
                             return;
                         }
                         println!("closing ticket in channel: {}", guild_channel.name.to_lowercase());
-                        match commands::close_ticket(&ctx, command.channel_id).await {
+                        match commands::close_ticket(&ctx, command.channel_id, self.config.transcript_channel_id.map(ChannelId::new), CloseReason::Manual, &self.config.ticket_close).await {
                             Ok(_) => {
                                 if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
                                     let _ = dm_channel.say(&ctx.http, "✅ Ticket has been closed.").await;
@@ -117,6 +258,20 @@ impl EventHandler for Handler {
                         }
                     }
                 },
+                "ticketpanel" => {
+                    if !command.member.as_deref().is_some_and(|member| is_moderator(&self.config, member)) {
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, "🛑 You do not have permission to post the ticket panel.").await;
+                        }
+                        return;
+                    }
+                    if let Err(e) = commands::post_ticket_panel(&ctx, command.channel_id, &config::get_ticket_types(&self.config)).await {
+                        eprintln!("🛑 Error posting ticket panel: {:?}", e);
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, "🛑 Could not post the ticket panel here.").await;
+                        }
+                    }
+                },
                 "pact" => {
                     if !balance::is_user_bound(&command.user.id.to_string()) {
                         if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
@@ -124,37 +279,53 @@ impl EventHandler for Handler {
                         }
                         return;
                     }
-                    let stake = if let Some(option) = command.data.options.get(0) {
+                    let stake = if let Some(option) = command.data.options.first() {
                         match &option.value {
-                            CommandDataOptionValue::Number(n) => *n,
-                            _ => 0.0,
+                            CommandDataOptionValue::String(s) => match s.parse::<Money>() {
+                                Ok(stake) => stake,
+                                Err(e) => {
+                                    if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                                        let _ = dm_channel.say(&ctx.http, format!("🛑 Invalid stake: {}.", e)).await;
+                                    }
+                                    return;
+                                }
+                            },
+                            _ => Money::ZERO,
                         }
                     } else {
-                        0.0
+                        Money::ZERO
                     };
-                    if stake <= 0.0 {
+                    if !stake.is_positive() {
                         if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
                             let _ = dm_channel.say(&ctx.http, "🛑 Please specify a valid stake (> 0) 🪙").await;
                         }
                         return;
                     }
-                    let user_data = balance::load_user_data(&command.user.id.to_string());
-                    if stake > user_data.balance {
+                    // An unreadable account is reported by `perform_pact` itself.
+                    if let Ok(user_data) = balance::load_user_data(&command.user.id.to_string())
+                        && stake > user_data.balance
+                    {
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, format!("🛑 Your stake ({}) exceeds your balance ({}) 🪙", stake, user_data.balance)).await;
+                        }
+                        return;
+                    }
+                    let _ = balance::perform_pact_dm(&ctx, &command.user, stake, &self.config.pact).await;
+                },
+                "daily" => {
+                    if !balance::is_user_bound(&command.user.id.to_string()) {
                         if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
-                            let _ = dm_channel.say(&ctx.http, format!("🛑 Your stake ({:.2}) exceeds your balance ({:.2}) 🪙", stake, user_data.balance)).await;
+                            let _ = dm_channel.say(&ctx.http, "🛑 Your account is not bound. please use /bind first.").await;
                         }
                         return;
                     }
-                    let result = balance::perform_pact(&command.user.id.to_string(), stake);
+                    let result = balance::claim_daily(&command.user.id.to_string(), &self.config.daily);
                     if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
                         let _ = dm_channel.say(&ctx.http, result).await;
                     }
                 },
                 "bind" => {
-                    let result = balance::bind_user(&command.user.id.to_string());
-                    if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
-                        let _ = dm_channel.say(&ctx.http, result).await;
-                    }
+                    let _ = balance::bind_user_dm(&ctx, &command.user).await;
                 },
                 "balance" => {
                     if !balance::is_user_bound(&command.user.id.to_string()) {
@@ -163,7 +334,32 @@ impl EventHandler for Handler {
                         }
                         return;
                     }
-                    let result = balance::get_balance(&command.user.id.to_string());
+                    let _ = balance::get_balance_dm(&ctx, &command.user).await;
+                },
+                "history" => {
+                    if !balance::is_user_bound(&command.user.id.to_string()) {
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, "🛑 Your account is not bound. please use /bind first.").await;
+                        }
+                        return;
+                    }
+                    let page = command.data.options.iter()
+                        .find(|opt| opt.name == "page")
+                        .and_then(|opt| opt.value.as_i64())
+                        .unwrap_or(1)
+                        .max(1) as usize;
+                    let result = balance::get_history(&command.user.id.to_string(), page);
+                    if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                        let _ = dm_channel.say(&ctx.http, result).await;
+                    }
+                },
+                "leaderboard" => {
+                    let limit = command.data.options.iter()
+                        .find(|opt| opt.name == "limit")
+                        .and_then(|opt| opt.value.as_i64())
+                        .map(|limit| limit.max(1) as usize)
+                        .unwrap_or(balance::LEADERBOARD_DEFAULT_SIZE);
+                    let result = balance::get_leaderboard(&command.user.id.to_string(), limit);
                     if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
                         let _ = dm_channel.say(&ctx.http, result).await;
                     }
@@ -188,7 +384,15 @@ impl EventHandler for Handler {
 
                     let amount = if let Some(option) = command.data.options.iter().find(|opt| opt.name == "amount") {
                         match &option.value {
-                            CommandDataOptionValue::Number(n) => *n,
+                            CommandDataOptionValue::String(s) => match s.parse::<Money>() {
+                                Ok(amount) => amount,
+                                Err(e) => {
+                                    if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                                        let _ = dm_channel.say(&ctx.http, format!("🛑 Invalid amount: {}.", e)).await;
+                                    }
+                                    return;
+                                }
+                            },
                             _ => {
                                 if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
                                     let _ = dm_channel.say(&ctx.http, "🛑 Invalid amount value.").await;
@@ -203,38 +407,579 @@ impl EventHandler for Handler {
                         return;
                     };
 
-                    let sender_id = command.user.id.to_string();
-                    let recipient_id = recipient.to_string();
-
-                    let result_msg = balance::pay(&sender_id, &recipient_id, amount);
+                    let _ = balance::pay_dm(&ctx, &command.user, recipient, amount).await;
+                },
+                "fairness" => {
+                    let Some(subcommand) = command.data.options.first() else {
+                        return;
+                    };
+                    let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+                        return;
+                    };
+                    let option = |name: &str| options.iter().find(|opt| opt.name == name).map(|opt| &opt.value);
+                    let user_id = command.user.id.to_string();
 
-                    if result_msg.starts_with("✅") {
-                        let dm_message_sender = format!(
-                            "✅ Successfully transferred {:.2} 🪙 to {}",
-                            amount,
-                            recipient.mention(),
-                        );
+                    let result = if subcommand.name == "verify" {
+                        let server_seed = option("server_seed").and_then(|value| value.as_str()).unwrap_or_default();
+                        let client_seed = option("client_seed").and_then(|value| value.as_str()).unwrap_or_default();
+                        let nonce = option("nonce").and_then(|value| value.as_i64()).unwrap_or(0).max(0) as u64;
+                        balance::verify_pact_roll(server_seed, client_seed, nonce, &self.config.pact)
+                    } else {
+                        match subcommand.name.as_str() {
+                            "show" => balance::fairness_status(&user_id),
+                            "seed" => {
+                                let client_seed = option("client_seed").and_then(|value| value.as_str()).unwrap_or_default();
+                                balance::set_client_seed(&user_id, client_seed)
+                            }
+                            "rotate" => balance::rotate_server_seed(&user_id),
+                            _ => return,
+                        }
+                    };
+                    if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                        let _ = dm_channel.say(&ctx.http, result).await;
+                    }
+                },
+                "apikey" => {
+                    if !command.member.as_deref().is_some_and(|member| is_moderator(&self.config, member)) {
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, "🛑 You do not have permission to manage API keys.").await;
+                        }
+                        return;
+                    }
+                    let Some(subcommand) = command.data.options.first() else {
+                        return;
+                    };
+                    let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+                        return;
+                    };
+                    let option = |name: &str| options.iter().find(|opt| opt.name == name).and_then(|opt| opt.value.as_str());
 
+                    let result = match subcommand.name.as_str() {
+                        "create" => match api_keys::parse_scopes(option("scopes").unwrap_or_default()) {
+                            Err(e) => format!("🛑 Invalid scopes: {}.", e),
+                            Ok(scopes) => match api_keys::create_key(option("name").unwrap_or_default(), scopes, command.user.id.get()) {
+                                Ok((key, secret)) => {
+                                    println!("🔑 API key {} created by {}", key.id, command.user.id);
+                                    format!("🔑 API key `{}` created. Store this secret now, it will not be shown again:\n`{}`", key.id, secret)
+                                }
+                                Err(e) => {
+                                    eprintln!("🛑 Failed to create API key: {:?}", e);
+                                    "🛑 Could not create the API key, please try again later.".to_string()
+                                }
+                            },
+                        },
+                        "revoke" => {
+                            let id = option("id").unwrap_or_default().trim();
+                            match api_keys::revoke_key(id) {
+                                Ok(Some(key)) => {
+                                    println!("🔑 API key {} revoked by {}", key.id, command.user.id);
+                                    format!("✅ API key `{}` ({}) revoked.", key.id, key.name)
+                                }
+                                Ok(None) => format!("🛑 No active API key with id `{}`.", id),
+                                Err(e) => {
+                                    eprintln!("🛑 Failed to revoke API key {}: {:?}", id, e);
+                                    "🛑 Could not revoke the API key, please try again later.".to_string()
+                                }
+                            }
+                        },
+                        "list" => api_keys::keys_summary(),
+                        _ => return,
+                    };
+                    if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                        let _ = dm_channel.say(&ctx.http, result).await;
+                    }
+                },
+                "eco" => {
+                    if !command.member.as_deref().is_some_and(|member| is_moderator(&self.config, member)) {
                         if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
-                            let _ = dm_channel.say(&ctx.http, dm_message_sender).await;
+                            let _ = dm_channel.say(&ctx.http, "🛑 You do not have permission to manage the economy.").await;
                         }
+                        return;
+                    }
+                    let Some(subcommand) = command.data.options.first() else {
+                        return;
+                    };
+                    let CommandDataOptionValue::SubCommand(options) = &subcommand.value else {
+                        return;
+                    };
+                    let option = |name: &str| options.iter().find(|opt| opt.name == name).map(|opt| &opt.value);
 
-                        if let Ok(recipient_dm) = recipient.create_dm_channel(&ctx.http).await {
-                            let dm_message_recipient = format!(
-                                "💸 You have received {:.2} 🪙 from {}",
-                                amount,
-                                command.user.mention()
-                            );
-                            let _ = recipient_dm.say(&ctx.http, dm_message_recipient).await;
+                    let Some(CommandDataOptionValue::User(target)) = option("user") else {
+                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, "🛑 Invalid target user.").await;
                         }
+                        return;
+                    };
+                    let reason = option("reason").and_then(|value| value.as_str()).unwrap_or_default();
+
+                    let action = if subcommand.name == "freeze" {
+                        AdminAction::Freeze(option("frozen").and_then(|value| value.as_bool()).unwrap_or(true))
                     } else {
-                        if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
-                            let _ = dm_channel.say(&ctx.http, result_msg).await;
+                        let amount = match option("amount").and_then(|value| value.as_str()).map(str::parse::<Money>) {
+                            Some(Ok(amount)) => amount,
+                            Some(Err(e)) => {
+                                if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                                    let _ = dm_channel.say(&ctx.http, format!("🛑 Invalid amount: {}.", e)).await;
+                                }
+                                return;
+                            }
+                            None => {
+                                if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                                    let _ = dm_channel.say(&ctx.http, "🛑 Amount not provided.").await;
+                                }
+                                return;
+                            }
+                        };
+                        match subcommand.name.as_str() {
+                            "grant" => AdminAction::Grant(amount),
+                            "revoke" => AdminAction::Revoke(amount),
+                            "set" => AdminAction::Set(amount),
+                            _ => return,
                         }
+                    };
+
+                    let result = balance::admin_adjust(&target.to_string(), action, &command.user.id.to_string(), reason);
+                    if let Ok(dm_channel) = command.user.create_dm_channel(&ctx.http).await {
+                        let _ = dm_channel.say(&ctx.http, result).await;
                     }
                 },
                 _ => {}
             }
         }
     }
-}
\ No newline at end of file
+}
+
+impl Handler {
+    /// `/ticket` subcommands that act on the ticket of the current channel.
+    async fn manage_ticket(
+        &self,
+        ctx: &Context,
+        command: &CommandInteraction,
+        subcommand: &str,
+        options: &[CommandDataOption],
+    ) -> String {
+        let Some(ticket) = tickets::find_by_channel(command.channel_id.get()) else {
+            return "🛑 This channel is not a ticket.".to_string();
+        };
+        let mod_roles = config::get_ticket_roles(&self.config, ticket.ticket_type.as_deref());
+        let is_mod = command.member.as_deref()
+            .is_some_and(|member| is_ticket_moderator(&self.config, member, ticket.ticket_type.as_deref()));
+        if !is_mod {
+            return "🛑 Only moderators can manage this ticket.".to_string();
+        }
+        match (subcommand, ticket.status) {
+            ("reopen", TicketStatus::Archived) => {
+                let ticket_type = ticket.ticket_type.as_deref()
+                    .and_then(|name| config::find_ticket_type(&self.config, Some(name)));
+                return match commands::reopen_ticket(ctx, &ticket, ticket_type.as_ref()).await {
+                    Ok(_) => {
+                        let _ = command.channel_id.say(&ctx.http, format!("📂 <@{}> reopened this ticket for <@{}>.", command.user.id, ticket.opener_id)).await;
+                        format!("✅ Ticket #{} reopened.", ticket.id)
+                    }
+                    Err(e) => {
+                        eprintln!("🛑 Error reopening ticket #{}: {:?}", ticket.id, e);
+                        "🛑 Failed to reopen the ticket, please try again later.".to_string()
+                    }
+                };
+            }
+            ("reopen", _) => return "🛑 Only archived tickets can be reopened.".to_string(),
+            ("delete", TicketStatus::Archived) => {
+                // The transcript was already delivered when the ticket was archived.
+                let reason = ticket.close_reason.unwrap_or(CloseReason::Manual);
+                return match commands::delete_ticket(ctx, command.channel_id, reason).await {
+                    Ok(_) => format!("✅ Ticket #{} deleted.", ticket.id),
+                    Err(e) => {
+                        eprintln!("🛑 Error deleting ticket #{}: {:?}", ticket.id, e);
+                        "🛑 Failed to delete the ticket, please try again later.".to_string()
+                    }
+                };
+            }
+            ("delete", TicketStatus::Open) => {
+                let settings = TicketCloseConfig { mode: CloseMode::Delete, archive_category_id: None };
+                let transcript_channel_id = self.config.transcript_channel_id.map(ChannelId::new);
+                return match commands::close_ticket(ctx, command.channel_id, transcript_channel_id, CloseReason::Manual, &settings).await {
+                    Ok(_) => format!("✅ Ticket #{} deleted.", ticket.id),
+                    Err(e) => {
+                        eprintln!("🛑 Error deleting ticket #{}: {:?}", ticket.id, e);
+                        "🛑 Failed to delete the ticket, please try again later.".to_string()
+                    }
+                };
+            }
+            // Its transcript is already delivered, so a note would never be seen.
+            ("note", TicketStatus::Archived) => {
+                return "🛑 This ticket is archived and its transcript was already sent. Reopen it to add a note.".to_string();
+            }
+            (_, TicketStatus::Open) | ("tag", TicketStatus::Archived) => {}
+            _ => return "🛑 This channel is not an open ticket.".to_string(),
+        }
+        let option = |name: &str| options.iter().find(|opt| opt.name == name).map(|opt| &opt.value);
+
+        match subcommand {
+            "claim" => match ticket.assigned_to {
+                Some(assignee) => format!("🛑 This ticket is already claimed by <@{}>.", assignee),
+                None => self.reassign_ticket(ctx, &ticket, Some(command.user.id), &mod_roles, command.user.id).await,
+            },
+            "unclaim" => match ticket.assigned_to {
+                None => "🛑 This ticket is not claimed.".to_string(),
+                Some(assignee) if assignee != command.user.id.get() => {
+                    format!("🛑 Only <@{}> can unclaim this ticket, use /ticket assign to hand it off.", assignee)
+                }
+                Some(_) => self.reassign_ticket(ctx, &ticket, None, &mod_roles, command.user.id).await,
+            },
+            "assign" => {
+                let Some(CommandDataOptionValue::User(target)) = option("moderator") else {
+                    return "🛑 Invalid target user.".to_string();
+                };
+                let target_is_mod = command.data.resolved.members.get(target)
+                    .is_some_and(|member| holds_any_role(&member.roles, &mod_roles));
+                if !target_is_mod {
+                    return format!("🛑 <@{}> cannot manage this ticket.", target);
+                }
+                if ticket.assigned_to == Some(target.get()) {
+                    return format!("🛑 This ticket is already assigned to <@{}>.", target);
+                }
+                self.reassign_ticket(ctx, &ticket, Some(*target), &mod_roles, command.user.id).await
+            },
+            "note" => {
+                let text = option("text").and_then(|value| value.as_str()).unwrap_or_default().trim();
+                if text.is_empty() {
+                    return "🛑 The note is empty.".to_string();
+                }
+                match tickets::add_note(ticket.channel_id, command.user.id.get(), text) {
+                    Ok(_) => {
+                        println!("✅ Ticket #{}: note added by {}", ticket.id, command.user.id);
+                        format!("📝 Note saved on ticket #{}. Only staff will see it in the transcript.", ticket.id)
+                    }
+                    Err(e) => {
+                        eprintln!("🛑 Failed to save note on ticket #{}: {:?}", ticket.id, e);
+                        "🛑 Could not save the note, please try again later.".to_string()
+                    }
+                }
+            },
+            "tag" => {
+                let tag = tickets::normalize_tag(option("tag").and_then(|value| value.as_str()).unwrap_or_default());
+                if tag.is_empty() {
+                    return "🛑 The tag is empty.".to_string();
+                }
+                let remove = option("remove").and_then(|value| value.as_bool()).unwrap_or(false);
+                match tickets::set_tag(ticket.channel_id, &tag, !remove) {
+                    Ok(true) if remove => format!("🏷️ Removed tag `{}` from ticket #{}.", tag, ticket.id),
+                    Ok(true) => format!("🏷️ Tagged ticket #{} with `{}`.", ticket.id, tag),
+                    Ok(false) if remove => format!("🛑 Ticket #{} is not tagged `{}`.", ticket.id, tag),
+                    Ok(false) => format!("🛑 Ticket #{} is already tagged `{}`.", ticket.id, tag),
+                    Err(e) => {
+                        eprintln!("🛑 Failed to tag ticket #{}: {:?}", ticket.id, e);
+                        "🛑 Could not update the tags, please try again later.".to_string()
+                    }
+                }
+            },
+            "add" | "remove" => {
+                let Some(CommandDataOptionValue::User(target)) = option("user") else {
+                    return "🛑 Invalid target user.".to_string();
+                };
+                let channel_id = ChannelId::new(ticket.channel_id);
+                if subcommand == "add" {
+                    if let Err(e) = commands::add_ticket_member(ctx, channel_id, *target).await {
+                        eprintln!("🛑 Failed to add {} to ticket #{}: {:?}", target, ticket.id, e);
+                        return "🛑 Could not add the member, please try again later.".to_string();
+                    }
+                    println!("✅ Ticket #{}: {} added {}", ticket.id, command.user.id, target);
+                    let _ = channel_id.say(&ctx.http, format!("➕ <@{}> added to the ticket by <@{}>.", target, command.user.id)).await;
+                    return format!("✅ Added <@{}> to ticket #{}.", target, ticket.id);
+                }
+                let target_is_staff = *target == ctx.cache.current_user().id
+                    || ticket.assigned_to == Some(target.get())
+                    || command.data.resolved.members.get(target)
+                        .is_some_and(|member| holds_any_role(&member.roles, &mod_roles));
+                if target.get() == ticket.opener_id {
+                    return "🛑 The opener cannot be removed from their own ticket.".to_string();
+                }
+                if target_is_staff {
+                    return format!("🛑 <@{}> is staff and cannot be removed from this ticket.", target);
+                }
+                if let Err(e) = commands::remove_ticket_member(ctx, channel_id, *target).await {
+                    eprintln!("🛑 Failed to remove {} from ticket #{}: {:?}", target, ticket.id, e);
+                    return "🛑 Could not remove the member, please try again later.".to_string();
+                }
+                println!("✅ Ticket #{}: {} removed {}", ticket.id, command.user.id, target);
+                let _ = channel_id.say(&ctx.http, format!("➖ <@{}> removed from the ticket by <@{}>.", target, command.user.id)).await;
+                format!("✅ Removed <@{}> from ticket #{}.", target, ticket.id)
+            },
+            _ => "🛑 Unknown ticket command.".to_string(),
+        }
+    }
+
+    async fn reassign_ticket(
+        &self,
+        ctx: &Context,
+        ticket: &Ticket,
+        assignee: Option<UserId>,
+        mod_roles: &[RoleId],
+        actor: UserId,
+    ) -> String {
+        let channel_id = ChannelId::new(ticket.channel_id);
+        if let Err(e) = commands::assign_ticket(ctx, ticket, assignee, mod_roles).await {
+            eprintln!("🛑 Failed to reassign ticket #{}: {:?}", ticket.id, e);
+            return "🛑 Could not update the ticket, please try again later.".to_string();
+        }
+        let notice = match assignee {
+            Some(assignee) if assignee == actor => format!("🙋 <@{}> has claimed this ticket.", assignee),
+            Some(assignee) => format!("🙋 <@{}> assigned this ticket to <@{}>.", actor, assignee),
+            None => format!("👋 <@{}> released this ticket.", actor),
+        };
+        let _ = channel_id.say(&ctx.http, &notice).await;
+        println!("✅ Ticket #{}: {}", ticket.id, notice);
+        match assignee {
+            Some(assignee) => format!("✅ Ticket #{} is now assigned to <@{}>.", ticket.id, assignee),
+            None => format!("✅ Ticket #{} is open to every moderator again.", ticket.id),
+        }
+    }
+
+    /// Buttons and menus from the ticket panel and ticket channels. Every
+    /// interaction is deferred first because creating or closing a ticket can
+    /// outlast Discord's three second response window.
+    async fn handle_component(&self, ctx: &Context, component: ComponentInteraction) {
+        // Rating buttons arrive from the opener's DMs, outside any guild.
+        let rating = component.data.custom_id
+            .strip_prefix(commands::RATE_TICKET_BUTTON)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|rest| rest.split_once(':'))
+            .and_then(|(ticket_id, stars)| Some((ticket_id.parse::<u32>().ok()?, stars.parse::<u8>().ok()?)));
+        if let Some((ticket_id, stars)) = rating {
+            let response = match tickets::record_rating(ticket_id, component.user.id.get(), stars) {
+                Ok(_) => CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .content(format!("✅ You rated ticket #{} {}. Want to tell us more?", ticket_id, "⭐".repeat(stars as usize)))
+                        .components(vec![commands::feedback_button(ticket_id)])
+                ),
+                Err(message) => CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(message)),
+            };
+            if let Err(e) = component.create_response(&ctx.http, response).await {
+                eprintln!("🛑 Failed to answer rating of ticket #{}: {:?}", ticket_id, e);
+            }
+            return;
+        }
+        let feedback_ticket = component.data.custom_id
+            .strip_prefix(commands::FEEDBACK_BUTTON)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|ticket_id| ticket_id.parse::<u32>().ok());
+        if let Some(ticket_id) = feedback_ticket {
+            let modal = CreateInteractionResponse::Modal(commands::feedback_modal(ticket_id));
+            if let Err(e) = component.create_response(&ctx.http, modal).await {
+                eprintln!("🛑 Failed to show feedback form: {:?}", e);
+            }
+            return;
+        }
+
+        let Some(guild_id) = component.guild_id else {
+            return;
+        };
+        let open_type = component.data.custom_id
+            .strip_prefix(commands::OPEN_TICKET_BUTTON)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .map(|type_name| config::find_ticket_type(&self.config, Some(type_name)));
+        // A modal has to be the first response, so it is shown before deferring.
+        // The limits are checked again once the form is submitted.
+        if let Some(Some(ticket_type)) = &open_type
+            && !ticket_type.questions.is_empty()
+        {
+            let response = match tickets::open_refusal(component.user.id.get(), &self.config.ticket_limits) {
+                Some(refusal) => CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new().content(refusal).ephemeral(true),
+                ),
+                None => CreateInteractionResponse::Modal(commands::intake_modal(ticket_type)),
+            };
+            if let Err(e) = component.create_response(&ctx.http, response).await {
+                eprintln!("🛑 Failed to show intake form: {:?}", e);
+            }
+            return;
+        }
+        if let Err(e) = component.defer_ephemeral(&ctx.http).await {
+            eprintln!("🛑 Failed to acknowledge interaction {}: {:?}", component.data.custom_id, e);
+            return;
+        }
+        if let Some(ticket_type) = open_type {
+            let Some(ticket_type) = ticket_type else {
+                let _ = component.edit_response(&ctx.http, EditInteractionResponse::new().content("🛑 This ticket type no longer exists.")).await;
+                return;
+            };
+            let ticket_id = match tickets::reserve_id(component.user.id.get(), &self.config.ticket_limits) {
+                Ok(ticket_id) => ticket_id,
+                Err(refusal) => {
+                    let _ = component.edit_response(&ctx.http, EditInteractionResponse::new().content(refusal)).await;
+                    return;
+                }
+            };
+            let content = match commands::create_ticket_channel(
+                ctx,
+                guild_id,
+                component.user.id,
+                ticket_id,
+                &ticket_type,
+                &self.config,
+                Vec::new()
+            ).await {
+                Ok(channel_id) => format!("✅ Ticket created: <#{}>", channel_id),
+                Err(e) => {
+                    eprintln!("🛑 Error creating ticket: {:?}", e);
+                    "🛑 Could not create ticket, please try again later.".to_string()
+                }
+            };
+            let _ = component.edit_response(&ctx.http, EditInteractionResponse::new().content(content)).await;
+            return;
+        }
+
+        let ticket = match tickets::find_by_channel(component.channel_id.get()) {
+            Some(ticket) if ticket.status == TicketStatus::Open => ticket,
+            _ => {
+                let _ = component.edit_response(&ctx.http, EditInteractionResponse::new().content("🛑 This channel is not an open ticket.")).await;
+                return;
+            }
+        };
+        let is_mod = component.member.as_ref()
+            .is_some_and(|member| is_ticket_moderator(&self.config, member, ticket.ticket_type.as_deref()));
+        let is_opener = component.user.id.get() == ticket.opener_id;
+
+        let response = match component.data.custom_id.as_str() {
+            commands::CLOSE_TICKET_BUTTON => {
+                if !is_mod {
+                    EditInteractionResponse::new().content("🛑 You do not have permission to close this ticket.")
+                } else {
+                    println!("closing ticket #{} from button", ticket.id);
+                    let _ = component.edit_response(&ctx.http, EditInteractionResponse::new().content("📁 Closing ticket...")).await;
+                    if let Err(e) = commands::close_ticket(ctx, component.channel_id, self.config.transcript_channel_id.map(ChannelId::new), CloseReason::Manual, &self.config.ticket_close).await {
+                        eprintln!("🛑 Error closing ticket: {:?}", e);
+                        if let Ok(dm_channel) = component.user.create_dm_channel(&ctx.http).await {
+                            let _ = dm_channel.say(&ctx.http, "🛑 Failed to close the ticket, please try again later.").await;
+                        }
+                    }
+                    return;
+                }
+            },
+            commands::CLAIM_TICKET_BUTTON => {
+                if !is_mod {
+                    EditInteractionResponse::new().content("🛑 Only moderators can claim tickets.")
+                } else if let Some(assignee) = ticket.assigned_to {
+                    EditInteractionResponse::new().content(format!("🛑 This ticket is already claimed by <@{}>.", assignee))
+                } else {
+                    let mod_roles = config::get_ticket_roles(&self.config, ticket.ticket_type.as_deref());
+                    let result = self.reassign_ticket(ctx, &ticket, Some(component.user.id), &mod_roles, component.user.id).await;
+                    EditInteractionResponse::new().content(result)
+                }
+            },
+            commands::KEEP_OPEN_BUTTON => {
+                if !is_mod && !is_opener {
+                    EditInteractionResponse::new().content("🛑 Only the opener or a moderator can keep this ticket open.")
+                } else if let Err(e) = tickets::record_activity(ticket.channel_id) {
+                    eprintln!("🛑 Failed to keep ticket #{} open: {:?}", ticket.id, e);
+                    EditInteractionResponse::new().content("🛑 Could not keep the ticket open, please try again later.")
+                } else {
+                    let _ = component.message.delete(&ctx.http).await;
+                    let _ = component.channel_id.say(&ctx.http, format!("⏳ <@{}> kept this ticket open.", component.user.id)).await;
+                    EditInteractionResponse::new().content("✅ The ticket stays open.")
+                }
+            },
+            commands::ADD_USER_BUTTON => {
+                if !is_mod {
+                    EditInteractionResponse::new().content("🛑 You do not have permission to add members to this ticket.")
+                } else {
+                    EditInteractionResponse::new()
+                        .content("➕ Pick the members to add to this ticket.")
+                        .components(vec![commands::add_user_menu()])
+                }
+            },
+            commands::ADD_USER_SELECT => {
+                let ComponentInteractionDataKind::UserSelect { values } = &component.data.kind else {
+                    return;
+                };
+                if !is_mod {
+                    EditInteractionResponse::new().content("🛑 You do not have permission to add members to this ticket.")
+                } else {
+                    let mut added = Vec::new();
+                    for user_id in values {
+                        match commands::add_ticket_member(ctx, component.channel_id, *user_id).await {
+                            Ok(_) => {
+                                println!("✅ Ticket #{}: {} added {}", ticket.id, component.user.id, user_id);
+                                added.push(format!("<@{}>", user_id));
+                            }
+                            Err(e) => eprintln!("🛑 Failed to add {} to ticket #{}: {:?}", user_id, ticket.id, e),
+                        }
+                    }
+                    if added.is_empty() {
+                        EditInteractionResponse::new().content("🛑 Could not add anyone to this ticket.")
+                    } else {
+                        let _ = component.channel_id.say(&ctx.http, format!("➕ {} added to the ticket by <@{}>.", added.join(", "), component.user.id)).await;
+                        EditInteractionResponse::new().content(format!("✅ Added {}.", added.join(", ")))
+                    }
+                }
+            },
+            _ => return,
+        };
+        let _ = component.edit_response(&ctx.http, response).await;
+    }
+
+    /// Intake form submissions; the ticket is created with the answers.
+    async fn handle_modal(&self, ctx: &Context, modal: ModalInteraction) {
+        let feedback_ticket = modal.data.custom_id
+            .strip_prefix(commands::FEEDBACK_MODAL)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|ticket_id| ticket_id.parse::<u32>().ok());
+        if let Some(ticket_id) = feedback_ticket {
+            let feedback = modal.data.components.iter()
+                .flat_map(|row| row.components.iter())
+                .find_map(|component| match component {
+                    ActionRowComponent::InputText(input) => input.value.clone(),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            let result = tickets::record_feedback(ticket_id, modal.user.id.get(), &feedback);
+            let response = CreateInteractionResponseMessage::new().content(result);
+            if let Err(e) = modal.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await {
+                eprintln!("🛑 Failed to answer feedback on ticket #{}: {:?}", ticket_id, e);
+            }
+            return;
+        }
+
+        let Some(guild_id) = modal.guild_id else {
+            return;
+        };
+        let Some(type_name) = modal.data.custom_id
+            .strip_prefix(commands::INTAKE_MODAL)
+            .and_then(|rest| rest.strip_prefix(':'))
+        else {
+            return;
+        };
+        if let Err(e) = modal.defer_ephemeral(&ctx.http).await {
+            eprintln!("🛑 Failed to acknowledge intake form: {:?}", e);
+            return;
+        }
+        let Some(ticket_type) = config::find_ticket_type(&self.config, Some(type_name)) else {
+            let _ = modal.edit_response(&ctx.http, EditInteractionResponse::new().content("🛑 This ticket type no longer exists.")).await;
+            return;
+        };
+        let ticket_id = match tickets::reserve_id(modal.user.id.get(), &self.config.ticket_limits) {
+            Ok(ticket_id) => ticket_id,
+            Err(refusal) => {
+                let _ = modal.edit_response(&ctx.http, EditInteractionResponse::new().content(refusal)).await;
+                return;
+            }
+        };
+        let answers = commands::intake_answers(&ticket_type, &modal.data.components);
+        let content = match commands::create_ticket_channel(
+            ctx,
+            guild_id,
+            modal.user.id,
+            ticket_id,
+            &ticket_type,
+            &self.config,
+            answers
+        ).await {
+            Ok(channel_id) => format!("✅ Ticket created: <#{}>", channel_id),
+            Err(e) => {
+                eprintln!("🛑 Error creating ticket: {:?}", e);
+                "🛑 Could not create ticket, please try again later.".to_string()
+            }
+        };
+        let _ = modal.edit_response(&ctx.http, EditInteractionResponse::new().content(content)).await;
+    }
+}
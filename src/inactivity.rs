@@ -0,0 +1,70 @@
+use serenity::prelude::*;
+use serenity::model::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::balance;
+use crate::commands;
+use crate::config::{InactivityConfig, TicketCloseConfig};
+use crate::tickets::{self, CloseReason};
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Starts the background sweep. `ready` fires again after every reconnect,
+/// so only the first call spawns the task.
+pub fn spawn(
+    ctx: Context,
+    config: InactivityConfig,
+    transcript_channel_id: Option<ChannelId>,
+    close_settings: TicketCloseConfig,
+) {
+    if !config.enabled || STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    println!("⏳ Inactivity sweep started: warn after {}s, close {}s later", config.idle_secs, config.grace_secs);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.check_interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            sweep(&ctx, &config, transcript_channel_id, &close_settings).await;
+        }
+    });
+}
+
+/// Warns open tickets that went quiet and closes those whose warning expired.
+async fn sweep(
+    ctx: &Context,
+    config: &InactivityConfig,
+    transcript_channel_id: Option<ChannelId>,
+    close_settings: &TicketCloseConfig,
+) {
+    let now = balance::current_unix_time();
+    for ticket in tickets::open_tickets() {
+        match ticket.inactivity_warned_at {
+            None if now.saturating_sub(ticket.last_activity()) >= config.idle_secs => {
+                if let Err(e) = commands::warn_inactive_ticket(ctx, &ticket, config.grace_secs).await {
+                    if commands::is_unknown_channel(&e) {
+                        commands::forget_deleted_channel(ChannelId::new(ticket.channel_id));
+                        continue;
+                    }
+                    eprintln!("🛑 Failed to warn inactive ticket #{}: {:?}", ticket.id, e);
+                    continue;
+                }
+                match tickets::update(ticket.channel_id, |ticket| ticket.inactivity_warned_at = Some(now)) {
+                    Ok(_) => println!("⏳ Ticket #{} warned for inactivity", ticket.id),
+                    Err(e) => eprintln!("🛑 Failed to record inactivity warning of ticket #{}: {:?}", ticket.id, e),
+                }
+            }
+            Some(warned_at) if now.saturating_sub(warned_at) >= config.grace_secs => {
+                println!("closing ticket #{} for inactivity", ticket.id);
+                let channel_id = ChannelId::new(ticket.channel_id);
+                match commands::close_ticket(ctx, channel_id, transcript_channel_id, CloseReason::Inactivity, close_settings).await {
+                    Err(e) if commands::is_unknown_channel(&e) => commands::forget_deleted_channel(channel_id),
+                    Err(e) => eprintln!("🛑 Error closing inactive ticket #{}: {:?}", ticket.id, e),
+                    Ok(()) => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Serialize, Deserialize};
+
+use crate::balance::current_unix_time;
+use crate::fairness::PactProof;
+use crate::money::Money;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// Balance carried over from before the ledger existed.
+    Opening,
+    Bind,
+    PactWin,
+    PactLoss,
+    PaymentSent,
+    PaymentReceived,
+    DailyReward,
+    AdminGrant,
+    AdminRevoke,
+    AdminSet,
+    AdminFreeze,
+    AdminUnfreeze,
+}
+
+impl EntryKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EntryKind::Opening => "📜 Opening balance",
+            EntryKind::Bind => "🖇️ Account bound",
+            EntryKind::PactWin => "✅ Pact won",
+            EntryKind::PactLoss => "🛑 Pact lost",
+            EntryKind::PaymentSent => "💸 Payment sent",
+            EntryKind::PaymentReceived => "💸 Payment received",
+            EntryKind::DailyReward => "🎁 Daily reward",
+            EntryKind::AdminGrant => "🛡️ Granted by staff",
+            EntryKind::AdminRevoke => "🛡️ Revoked by staff",
+            EntryKind::AdminSet => "🛡️ Set by staff",
+            EntryKind::AdminFreeze => "🧊 Account frozen",
+            EntryKind::AdminUnfreeze => "🧊 Account unfrozen",
+        }
+    }
+}
+
+/// One immutable balance change of a single account. `id` is assigned by
+/// the storage backend when the entry is committed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LedgerEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub user_id: String,
+    pub kind: EntryKind,
+    pub counterparty: Option<String>,
+    /// Signed change to the account; negative for outflows.
+    pub amount: Money,
+    pub balance_after: Money,
+    pub actor: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Inputs of a provably fair pact roll.
+    #[serde(default)]
+    pub proof: Option<PactProof>,
+}
+
+impl LedgerEntry {
+    pub fn new(user_id: &str, kind: EntryKind, amount: Money, balance_after: Money, actor: &str) -> Self {
+        LedgerEntry {
+            id: 0,
+            timestamp: current_unix_time(),
+            user_id: user_id.to_string(),
+            kind,
+            counterparty: None,
+            amount,
+            balance_after,
+            actor: actor.to_string(),
+            reason: None,
+            proof: None,
+        }
+    }
+
+    pub fn with_counterparty(mut self, counterparty: &str) -> Self {
+        self.counterparty = Some(counterparty.to_string());
+        self
+    }
+
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    pub fn with_proof(mut self, proof: Option<PactProof>) -> Self {
+        self.proof = proof;
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LedgerMismatch {
+    pub user_id: String,
+    pub replayed: Money,
+    pub stored: Money,
+}
\ No newline at end of file
@@ -1,7 +1,6 @@
 use dotenvy::dotenv;
 use std::env;
-use std::thread;
-use actix_web::rt::System;
+use std::path::Path;
 use serenity::prelude::*;
 use serenity::Client as DiscordClient;
 
@@ -9,7 +8,16 @@ mod config;
 mod handler;
 mod commands;
 mod api;
+mod api_keys;
 mod balance;
+mod fairness;
+mod inactivity;
+mod ledger;
+mod migrations;
+mod money;
+mod storage;
+mod tickets;
+mod transcript;
 
 use config::load_config;
 
@@ -22,9 +30,25 @@ async fn main() {
     if let Err(e) = balance::ensure_data_directory() {
         eprintln!("🛑 Error creating data directory: {:?}", e);
     }
+    balance::init_storage(&config.storage, Path::new(balance::DATA_DIRECTORY)).expect("🛑 Failed to initialize storage backend");
+    match balance::verify_ledger() {
+        Ok(mismatches) if mismatches.is_empty() => println!("✅ Ledger matches every account balance"),
+        Ok(mismatches) => {
+            for mismatch in mismatches {
+                eprintln!(
+                    "🛑 Ledger mismatch for {}: ledger replays to {}, account holds {}",
+                    mismatch.user_id, mismatch.replayed, mismatch.stored
+                );
+            }
+        }
+        Err(e) => eprintln!("🛑 Failed to verify ledger: {:?}", e),
+    }
+    tickets::init(Path::new(balance::DATA_DIRECTORY)).expect("🛑 Failed to load ticket store");
+    api_keys::init(Path::new(balance::DATA_DIRECTORY)).expect("🛑 Failed to load API keys");
 
     let token = env::var("DISCORD_TOKEN").expect("🛑 Missing DISCORD_TOKEN in .env");
-    let intents = GatewayIntents::GUILDS;
+    // Message content is needed to export ticket transcripts.
+    let intents = GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
     let handler = handler::Handler { config: config.clone() };
 
     let mut bot = DiscordClient::builder(&token, intents)
@@ -32,12 +56,55 @@ async fn main() {
         .await
         .expect("🛑 Error creating client");
 
-    thread::spawn(|| {
-        let sys = System::new();
-        sys.block_on(api::start_api_server()).expect("🛑 API server failed");
+    // The API shares this runtime; stopping the bot also stops the API.
+    let api_server = if config.api.enabled {
+        Some(api::start_api_server(&config.api).expect("🛑 Failed to start API server"))
+    } else {
+        println!("🌐 API disabled");
+        None
+    };
+    let api_handle = api_server.as_ref().map(|server| server.handle());
+    let api_task = api_server.map(tokio::spawn);
+
+    let shard_manager = bot.shard_manager.clone();
+    tokio::spawn(async move {
+        match shutdown_signal().await {
+            Ok(()) => {
+                println!("🛑 Shutting down");
+                shard_manager.shutdown_all().await;
+            }
+            Err(e) => eprintln!("🛑 Failed to listen for shutdown signals: {:?}", e),
+        }
     });
 
     if let Err(e) = bot.start().await {
         eprintln!("🛑 Client error: {:?}", e);
     }
-}
\ No newline at end of file
+
+    if let Some(handle) = api_handle {
+        handle.stop(true).await;
+    }
+    if let Some(task) = api_task {
+        match task.await {
+            Ok(Err(e)) => eprintln!("🛑 API server failed: {:?}", e),
+            Err(e) => eprintln!("🛑 API server task failed: {:?}", e),
+            Ok(Ok(())) => {}
+        }
+    }
+}
+
+/// Ctrl+C, or SIGTERM as sent by systemd and `docker stop`.
+#[cfg(unix)]
+async fn shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
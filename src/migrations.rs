@@ -0,0 +1,96 @@
+use serde_json::Value;
+use std::fmt;
+
+use crate::balance::UserData;
+use crate::money::Money;
+
+/// Version written by this build. Bump it together with a new step in `MIGRATIONS`.
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades a version `n` record to version `n + 1`.
+const MIGRATIONS: [Migration; CURRENT_SCHEMA_VERSION as usize] = [migrate_v0_to_v1];
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The record cannot be read at all and should be set aside.
+    Corrupt(String),
+    /// The record was written by a newer build and must not be touched.
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Corrupt(reason) => write!(f, "corrupt user data: {}", reason),
+            DecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "user data has schema version {} but this build only supports up to {}",
+                version, CURRENT_SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// v0 had no `schema_version` and stored `balance` as floating-point coins.
+/// Integer balances come from builds that already wrote minor units.
+fn migrate_v0_to_v1(record: &mut Value) -> Result<(), String> {
+    let balance = record.get_mut("balance").ok_or("missing balance")?;
+    if balance.is_f64() {
+        let coins = balance.as_f64().ok_or("balance is not a number")?;
+        let money = Money::from_legacy_f64(coins).ok_or("balance is not a finite number")?;
+        *balance = Value::from(money.minor());
+    } else if !balance.is_i64() {
+        return Err("balance is not a number".to_string());
+    }
+    Ok(())
+}
+
+/// Parses a stored record of any known version and upgrades it to the current one.
+pub fn decode_user_data(raw: &str) -> Result<UserData, DecodeError> {
+    let mut record: Value = serde_json::from_str(raw).map_err(|e| DecodeError::Corrupt(e.to_string()))?;
+    if !record.is_object() {
+        return Err(DecodeError::Corrupt("record is not an object".to_string()));
+    }
+    let version = match record.get("schema_version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| DecodeError::Corrupt("invalid schema_version".to_string()))?,
+    };
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut record).map_err(DecodeError::Corrupt)?;
+    }
+    record["schema_version"] = Value::from(CURRENT_SCHEMA_VERSION);
+    serde_json::from_value(record).map_err(|e| DecodeError::Corrupt(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrates_v0_record() {
+        let data = decode_user_data(r#"{"balance":12.5,"last_pact":7}"#).unwrap();
+        assert_eq!(data.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(data.balance, Money::from_minor(1250));
+        assert_eq!(data.last_pact, 7);
+    }
+
+    #[test]
+    fn test_rejects_corrupt_and_future_records() {
+        assert!(matches!(decode_user_data("{\"balance\":"), Err(DecodeError::Corrupt(_))));
+        assert!(matches!(decode_user_data(r#"{"last_pact":7}"#), Err(DecodeError::Corrupt(_))));
+        assert!(matches!(
+            decode_user_data(r#"{"schema_version":99,"balance":0,"last_pact":0}"#),
+            Err(DecodeError::UnsupportedVersion(99))
+        ));
+    }
+}
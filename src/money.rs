@@ -0,0 +1,281 @@
+use serde::{Serialize, Deserialize, Deserializer, Serializer};
+use serde::de::{self, Visitor};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Highest supported scale; keeps `10^decimals` well inside `i64`.
+pub const MAX_DECIMALS: u32 = 9;
+
+static DECIMALS: AtomicU32 = AtomicU32::new(2);
+
+/// Sets how many decimal places one coin is divided into. Stored amounts are
+/// minor units, so this must not change once balances exist.
+pub fn set_decimals(decimals: u32) {
+    DECIMALS.store(decimals.min(MAX_DECIMALS), Ordering::Relaxed);
+}
+
+pub fn decimals() -> u32 {
+    DECIMALS.load(Ordering::Relaxed)
+}
+
+fn scale() -> i64 {
+    10_i64.pow(decimals())
+}
+
+/// A coin amount stored as a whole number of minor units.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseMoneyError {
+    Empty,
+    Invalid,
+    Negative,
+    TooPrecise(u32),
+    Overflow,
+}
+
+impl fmt::Display for ParseMoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseMoneyError::Empty => write!(f, "amount is empty"),
+            ParseMoneyError::Invalid => write!(f, "amount is not a number"),
+            ParseMoneyError::Negative => write!(f, "amount cannot be negative"),
+            ParseMoneyError::TooPrecise(decimals) => write!(f, "amount has more than {} decimal places", decimals),
+            ParseMoneyError::Overflow => write!(f, "amount is too large"),
+        }
+    }
+}
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_minor(minor: i64) -> Self {
+        Money(minor)
+    }
+
+    /// Whole coins at the current scale.
+    pub fn from_coins(coins: i64) -> Self {
+        Money(coins.saturating_mul(scale()))
+    }
+
+    pub fn minor(self) -> i64 {
+        self.0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn negate(self) -> Money {
+        Money(-self.0)
+    }
+
+    pub fn checked_add(self, other: Money) -> Option<Money> {
+        self.0.checked_add(other.0).map(Money)
+    }
+
+    /// Refuses results below zero as well as overflow.
+    pub fn checked_sub(self, other: Money) -> Option<Money> {
+        self.0.checked_sub(other.0).filter(|&minor| minor >= 0).map(Money)
+    }
+
+    /// Whole coins as a float, for clients that predate minor units.
+    pub fn to_coins_f64(self) -> f64 {
+        self.0 as f64 / scale() as f64
+    }
+
+    /// `percent`% of this amount, rounded down to a whole minor unit.
+    pub fn percent(self, percent: u32) -> Money {
+        Money((self.0 as i128 * percent as i128 / 100) as i64)
+    }
+
+    /// Converts a legacy floating-point amount, rounding to the configured scale.
+    pub(crate) fn from_legacy_f64(value: f64) -> Option<Money> {
+        let minor = (value * scale() as f64).round();
+        if !minor.is_finite() || minor.abs() >= i64::MAX as f64 {
+            return None;
+        }
+        Some(Money(minor as i64))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = decimals();
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let scale = scale() as u64;
+        if decimals == 0 {
+            return write!(f, "{}{}", sign, abs);
+        }
+        write!(f, "{}{}.{:0width$}", sign, abs / scale, abs % scale, width = decimals as usize)
+    }
+}
+
+impl FromStr for Money {
+    type Err = ParseMoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ParseMoneyError::Empty);
+        }
+        if s.starts_with('-') {
+            return Err(ParseMoneyError::Negative);
+        }
+        let (whole, fraction) = match s.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (s, ""),
+        };
+        let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !all_digits(whole) || !all_digits(fraction) {
+            return Err(ParseMoneyError::Invalid);
+        }
+        let decimals = decimals();
+        if fraction.len() > decimals as usize {
+            return Err(ParseMoneyError::TooPrecise(decimals));
+        }
+
+        let whole: i64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| ParseMoneyError::Overflow)? };
+        let mut fraction_minor: i64 = if fraction.is_empty() { 0 } else { fraction.parse().map_err(|_| ParseMoneyError::Invalid)? };
+        fraction_minor *= 10_i64.pow(decimals - fraction.len() as u32);
+        whole
+            .checked_mul(scale())
+            .and_then(|minor| minor.checked_add(fraction_minor))
+            .map(Money)
+            .ok_or(ParseMoneyError::Overflow)
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(self.0)
+    }
+}
+
+/// Integers are minor units. Floats are balances written before `Money`
+/// existed and are read as whole coins. Strings such as `"12.50"` are whole
+/// coins. Amounts written by people, as in `config.json`, use `coins` instead.
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MoneyVisitor;
+
+        impl<'de> Visitor<'de> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an amount in minor units or a decimal string")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Money, E> {
+                Ok(Money::from_minor(value))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Money, E> {
+                i64::try_from(value).map(Money::from_minor).map_err(|_| E::custom("amount is too large"))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Money, E> {
+                Money::from_legacy_f64(value).ok_or_else(|| E::custom("amount is not a finite number"))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Money, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+/// Serde adapter for amounts written by people, as in `config.json`. Every
+/// number or string is whole coins, so `10`, `10.0` and `"10"` all mean ten.
+pub mod coins {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(money: &Money, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&money.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        struct CoinsVisitor;
+
+        impl<'de> Visitor<'de> for CoinsVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an amount in whole coins, e.g. 10 or \"12.50\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Money, E> {
+                self.visit_str(&value.to_string())
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Money, E> {
+                self.visit_str(&value.to_string())
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Money, E> {
+                self.visit_str(&value.to_string())
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Money, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(CoinsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!("12.5".parse::<Money>().unwrap(), Money::from_minor(1250));
+        assert_eq!(".25".parse::<Money>().unwrap().to_string(), "0.25");
+        assert_eq!("0.001".parse::<Money>(), Err(ParseMoneyError::TooPrecise(2)));
+        assert_eq!("-1".parse::<Money>(), Err(ParseMoneyError::Negative));
+        assert_eq!("NaN".parse::<Money>(), Err(ParseMoneyError::Invalid));
+        assert_eq!("99999999999999999999".parse::<Money>(), Err(ParseMoneyError::Overflow));
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let a = Money::from_minor(10);
+        let b = Money::from_minor(20);
+        assert_eq!(a.checked_add(b), Some(Money::from_minor(30)));
+        assert_eq!(a.checked_sub(b), None);
+        assert_eq!(Money::from_minor(i64::MAX).checked_add(a), None);
+        assert_eq!(Money::from_coins(3), Money::from_minor(300));
+    }
+
+    #[test]
+    fn test_legacy_float_deserialize() {
+        let money: Money = serde_json::from_str("0.30000000000000004").unwrap();
+        assert_eq!(money, Money::from_minor(30));
+        let money: Money = serde_json::from_str("4200").unwrap();
+        assert_eq!(money.to_string(), "42.00");
+    }
+
+    #[test]
+    fn test_config_amounts_are_whole_coins() {
+        #[derive(Deserialize)]
+        struct Amount {
+            #[serde(with = "coins")]
+            amount: Money,
+        }
+        for raw in [r#"{"amount":10}"#, r#"{"amount":10.0}"#, r#"{"amount":"10"}"#] {
+            let parsed: Amount = serde_json::from_str(raw).unwrap();
+            assert_eq!(parsed.amount, Money::from_minor(1000));
+        }
+        let parsed: Amount = serde_json::from_str(r#"{"amount":2.5}"#).unwrap();
+        assert_eq!(parsed.amount, Money::from_minor(250));
+        assert!(serde_json::from_str::<Amount>(r#"{"amount":-1}"#).is_err());
+        assert!(serde_json::from_str::<Amount>(r#"{"amount":0.001}"#).is_err());
+    }
+}
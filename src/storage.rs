@@ -0,0 +1,921 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+use crate::balance::{current_unix_time, UserData};
+use crate::ledger::LedgerEntry;
+use crate::migrations::{self, DecodeError};
+use crate::money::Money;
+
+fn to_io_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::other(err)
+}
+
+/// A commit that failed after its journal was written. The batch is not
+/// lost: it is finished before storage is read or written again, so it must
+/// not be reported as rolled back.
+#[derive(Debug)]
+pub struct CommitPending(io::Error);
+
+impl fmt::Display for CommitPending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "commit is journaled but not yet applied: {}", self.0)
+    }
+}
+
+impl std::error::Error for CommitPending {}
+
+pub fn is_commit_pending(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<CommitPending>())
+}
+
+/// Persistence backend for per-user economy data.
+pub trait Storage: Send + Sync {
+    fn exists(&self, user_id: &str) -> io::Result<bool>;
+    /// Upgrades older records through `migrations`. A record that cannot be
+    /// decoded is moved to quarantine and reported as `InvalidData`.
+    fn load(&self, user_id: &str) -> io::Result<Option<UserData>>;
+    /// Writes every record and appends every ledger entry, or does nothing.
+    /// Entry ids are assigned here. An error that `is_commit_pending` means
+    /// the batch will still be applied in full.
+    fn commit(&self, writes: &[(&str, &UserData)], entries: &mut [LedgerEntry]) -> io::Result<()>;
+    /// Ledger entries in commit order, optionally only those of one account.
+    fn ledger(&self, user_id: Option<&str>) -> io::Result<Vec<LedgerEntry>>;
+    /// Newest-first slice of one account's entries and its total entry count.
+    fn ledger_page(&self, user_id: &str, offset: usize, limit: usize) -> io::Result<(Vec<LedgerEntry>, usize)>;
+    /// Accounts ordered by balance, highest first, and the total account count.
+    fn top_balances(&self, limit: usize, offset: usize) -> io::Result<(Vec<(String, Money)>, usize)>;
+    /// 1-based position of the account in the balance ranking.
+    fn rank(&self, user_id: &str) -> io::Result<Option<usize>>;
+    /// The provably fair pact rolled with this server seed hash and nonce.
+    fn pact_entry(&self, server_seed_hash: &str, nonce: u64) -> io::Result<Option<LedgerEntry>>;
+}
+
+/// Discord snowflakes are plain decimal u64s, so anything else is rejected
+/// before it can become part of a file path.
+pub fn is_valid_user_id(user_id: &str) -> bool {
+    !user_id.is_empty() && user_id.bytes().all(|b| b.is_ascii_digit()) && user_id.parse::<u64>().is_ok()
+}
+
+fn invalid_user_id(user_id: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid user id {:?}", user_id))
+}
+
+const JOURNAL_FILE: &str = "journal.json";
+const LEDGER_FILE: &str = "ledger.jsonl";
+const QUARANTINE_DIRECTORY: &str = "quarantine";
+
+#[derive(Deserialize)]
+struct Journal {
+    writes: Vec<(String, UserData)>,
+    entries: Vec<LedgerEntry>,
+}
+
+#[derive(Serialize)]
+struct JournalRef<'a> {
+    writes: &'a [(&'a str, &'a UserData)],
+    entries: &'a [LedgerEntry],
+}
+
+/// In-memory ranking of every account; ties are broken by user id.
+#[derive(Default)]
+struct BalanceIndex {
+    ranking: BTreeSet<(Reverse<Money>, String)>,
+    balances: HashMap<String, Money>,
+}
+
+impl BalanceIndex {
+    fn update(&mut self, user_id: &str, balance: Money) {
+        if let Some(old) = self.balances.insert(user_id.to_string(), balance) {
+            self.ranking.remove(&(Reverse(old), user_id.to_string()));
+        }
+        self.ranking.insert((Reverse(balance), user_id.to_string()));
+    }
+
+    fn rank(&self, user_id: &str) -> Option<usize> {
+        let balance = *self.balances.get(user_id)?;
+        Some(self.ranking.range(..(Reverse(balance), user_id.to_string())).count() + 1)
+    }
+}
+
+/// Byte range of every ledger line, grouped by account, so one account's
+/// history is read without parsing the whole file.
+#[derive(Default)]
+struct LedgerIndex {
+    lines: HashMap<String, Vec<(u64, usize)>>,
+    /// Pact lines keyed by the server seed hash and nonce of their proof.
+    proofs: HashMap<(String, u64), (u64, usize)>,
+    last_id: u64,
+}
+
+impl LedgerIndex {
+    fn push(&mut self, entry: IndexedEntry, offset: u64, len: usize) {
+        self.lines.entry(entry.user_id).or_default().push((offset, len));
+        if let Some(proof) = entry.proof {
+            self.proofs.insert((proof.server_seed_hash, proof.nonce), (offset, len));
+        }
+        self.last_id = self.last_id.max(entry.id);
+    }
+}
+
+/// The fields of a ledger line the index needs.
+#[derive(Deserialize)]
+struct IndexedEntry {
+    id: u64,
+    user_id: String,
+    #[serde(default)]
+    proof: Option<IndexedProof>,
+}
+
+#[derive(Deserialize)]
+struct IndexedProof {
+    server_seed_hash: String,
+    nonce: u64,
+}
+
+impl From<&LedgerEntry> for IndexedEntry {
+    fn from(entry: &LedgerEntry) -> Self {
+        IndexedEntry {
+            id: entry.id,
+            user_id: entry.user_id.clone(),
+            proof: entry.proof.as_ref().map(|proof| IndexedProof {
+                server_seed_hash: proof.server_seed_hash.clone(),
+                nonce: proof.nonce,
+            }),
+        }
+    }
+}
+
+/// One `<user_id>.json` file per user inside a directory, plus an
+/// append-only `ledger.jsonl`. Commits go through a write-ahead journal that
+/// `recover` replays after a crash, so a batch is either fully applied or
+/// not visible at all.
+pub struct JsonStorage {
+    directory: PathBuf,
+    /// Serializes commits and caches the highest ledger id once it is known.
+    last_entry_id: Mutex<Option<u64>>,
+    /// Built from a single directory scan on first use, then kept current by every write.
+    balance_index: Mutex<Option<BalanceIndex>>,
+    /// Built from a single ledger scan on first use, then kept current by every append.
+    ledger_index: Mutex<Option<LedgerIndex>>,
+}
+
+impl JsonStorage {
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        JsonStorage {
+            directory: directory.as_ref().to_path_buf(),
+            last_entry_id: Mutex::new(None),
+            balance_index: Mutex::new(None),
+            ledger_index: Mutex::new(None),
+        }
+    }
+
+    fn user_path(&self, user_id: &str) -> io::Result<PathBuf> {
+        if !is_valid_user_id(user_id) {
+            return Err(invalid_user_id(user_id));
+        }
+        Ok(self.directory.join(format!("{}.json", user_id)))
+    }
+
+    /// Ids of every `<user_id>.json` file in the directory.
+    pub fn user_ids(&self) -> io::Result<Vec<String>> {
+        if !self.directory.exists() {
+            return Ok(Vec::new());
+        }
+        let mut user_ids = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.is_file()
+                && path.extension().is_some_and(|ext| ext == "json")
+                // Also skips the journal, which is not named after a user.
+                && let Some(user_id) = path.file_stem().and_then(|stem| stem.to_str()).filter(|stem| is_valid_user_id(stem))
+            {
+                user_ids.push(user_id.to_string());
+            }
+        }
+        user_ids.sort();
+        Ok(user_ids)
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.directory.join(JOURNAL_FILE)
+    }
+
+    fn ledger_path(&self) -> PathBuf {
+        self.directory.join(LEDGER_FILE)
+    }
+
+    /// Moves an unreadable user file aside so it is neither lost nor overwritten.
+    fn quarantine(&self, user_id: &str, error: &DecodeError) -> io::Result<PathBuf> {
+        let quarantine_dir = self.directory.join(QUARANTINE_DIRECTORY);
+        fs::create_dir_all(&quarantine_dir)?;
+        let target = quarantine_dir.join(format!("{}-{}.json", user_id, current_unix_time()));
+        fs::rename(self.user_path(user_id)?, &target)?;
+        eprintln!("🛑 Quarantined data of user {} to {:?}: {}", user_id, target, error);
+        Ok(target)
+    }
+
+    /// Finishes a commit that was interrupted after its journal was written.
+    pub fn recover(&self) -> io::Result<()> {
+        let mut last_entry_id = self.last_entry_id.lock().unwrap_or_else(PoisonError::into_inner);
+        self.replay_journal()?;
+        *last_entry_id = None;
+        Ok(())
+    }
+
+    /// Completes a failed commit before anything reads the files it touches.
+    fn finish_pending_commit(&self) -> io::Result<()> {
+        if !self.journal_path().exists() {
+            return Ok(());
+        }
+        self.recover()
+    }
+
+    /// Caller must hold `last_entry_id`.
+    fn replay_journal(&self) -> io::Result<()> {
+        let journal_path = self.journal_path();
+        if !journal_path.exists() {
+            return Ok(());
+        }
+        let journal_str = fs::read_to_string(&journal_path)?;
+        match serde_json::from_str::<Journal>(&journal_str) {
+            Ok(journal) => {
+                let last_id = self.read_last_entry_id()?;
+                let pending: Vec<&LedgerEntry> = journal.entries.iter().filter(|e| e.id > last_id).collect();
+                self.append_entries(&pending)?;
+                for (user_id, data) in &journal.writes {
+                    self.write_user(user_id, data)?;
+                }
+                println!("✅ Replayed {} pending write(s) from storage journal", journal.writes.len());
+            }
+            // The journal is renamed into place whole, so an unreadable one was never applied.
+            Err(e) => eprintln!("🛑 Discarding incomplete storage journal: {:?}", e),
+        }
+        fs::remove_file(journal_path)
+    }
+
+    fn write_user(&self, user_id: &str, data: &UserData) -> io::Result<()> {
+        let json_data = serde_json::to_vec(data).map_err(to_io_error)?;
+        write_atomically(&self.user_path(user_id)?, &json_data)?;
+        let mut balance_index = self.balance_index.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = balance_index.as_mut() {
+            index.update(user_id, data.balance);
+        }
+        Ok(())
+    }
+
+    fn balance_index(&self) -> io::Result<MutexGuard<'_, Option<BalanceIndex>>> {
+        let mut balance_index = self.balance_index.lock().unwrap_or_else(PoisonError::into_inner);
+        if balance_index.is_none() {
+            let mut index = BalanceIndex::default();
+            for user_id in self.user_ids()? {
+                let path = self.user_path(&user_id)?;
+                match fs::read_to_string(&path).map(|s| migrations::decode_user_data(&s)) {
+                    Ok(Ok(data)) => index.update(&user_id, data.balance),
+                    _ => eprintln!("🛑 Skipping unreadable user file in balance index: {:?}", path),
+                }
+            }
+            *balance_index = Some(index);
+        }
+        Ok(balance_index)
+    }
+
+    fn read_last_entry_id(&self) -> io::Result<u64> {
+        Ok(self.ledger_index()?.as_ref().map_or(0, |index| index.last_id))
+    }
+
+    fn ledger_index(&self) -> io::Result<MutexGuard<'_, Option<LedgerIndex>>> {
+        let mut ledger_index = self.ledger_index.lock().unwrap_or_else(PoisonError::into_inner);
+        if ledger_index.is_none() {
+            let mut index = LedgerIndex::default();
+            let ledger_path = self.ledger_path();
+            if ledger_path.exists() {
+                let mut offset = 0;
+                for line in fs::read_to_string(ledger_path)?.split_inclusive('\n') {
+                    let content = line.trim_end_matches('\n');
+                    if !content.trim().is_empty() {
+                        let entry: IndexedEntry = serde_json::from_str(content).map_err(to_io_error)?;
+                        index.push(entry, offset, content.len());
+                    }
+                    offset += line.len() as u64;
+                }
+            }
+            *ledger_index = Some(index);
+        }
+        Ok(ledger_index)
+    }
+
+    /// Reads the ledger lines at `ranges`, in the given order.
+    fn read_entries<'a>(&self, ranges: impl Iterator<Item = &'a (u64, usize)>) -> io::Result<Vec<LedgerEntry>> {
+        let mut file = File::open(self.ledger_path())?;
+        let mut entries = Vec::new();
+        for &(offset, len) in ranges {
+            let mut line = vec![0; len];
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut line)?;
+            entries.push(serde_json::from_slice(&line).map_err(to_io_error)?);
+        }
+        Ok(entries)
+    }
+
+    fn read_ledger(&self) -> io::Result<Vec<LedgerEntry>> {
+        let ledger_path = self.ledger_path();
+        if !ledger_path.exists() {
+            return Ok(Vec::new());
+        }
+        fs::read_to_string(ledger_path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(to_io_error))
+            .collect()
+    }
+
+    fn append_entries(&self, entries: &[&LedgerEntry]) -> io::Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry).map_err(to_io_error)?);
+            lines.push('\n');
+        }
+        // Build the index first so it only has to cover earlier lines.
+        let mut ledger_index = self.ledger_index()?;
+        let mut file = OpenOptions::new().create(true).append(true).open(self.ledger_path())?;
+        let mut offset = file.metadata()?.len();
+        let written = file.write_all(lines.as_bytes()).and_then(|_| file.sync_all());
+        match (&written, ledger_index.as_mut()) {
+            (Ok(()), Some(index)) => {
+                for (entry, line) in entries.iter().zip(lines.lines()) {
+                    index.push(IndexedEntry::from(*entry), offset, line.len());
+                    offset += line.len() as u64 + 1;
+                }
+            }
+            // A partial append leaves the index unsure; rebuild it on next use.
+            _ => *ledger_index = None,
+        }
+        written
+    }
+}
+
+/// Reads a JSON document kept in a single file, or `T::default()` when the
+/// file does not exist yet. An unreadable file is an error rather than an
+/// empty document, so callers never overwrite data they could not read.
+pub fn load_json_file<T: DeserializeOwned + Default>(path: &Path) -> io::Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let data_str = fs::read_to_string(path)?;
+    serde_json::from_str(&data_str).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes `value` as pretty JSON through `write_atomically`, creating its directory first.
+pub fn save_json_file<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let json_data = serde_json::to_vec_pretty(value).map_err(to_io_error)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomically(path, &json_data)
+}
+
+/// Writes to a sibling temp file, syncs it and renames it over `path`.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(tmp_path, path)
+}
+
+impl Storage for JsonStorage {
+    fn exists(&self, user_id: &str) -> io::Result<bool> {
+        self.finish_pending_commit()?;
+        Ok(self.user_path(user_id)?.exists())
+    }
+
+    fn load(&self, user_id: &str) -> io::Result<Option<UserData>> {
+        self.finish_pending_commit()?;
+        let file_path = self.user_path(user_id)?;
+        if !file_path.exists() {
+            return Ok(None);
+        }
+        let data_str = fs::read_to_string(&file_path)?;
+        match migrations::decode_user_data(&data_str) {
+            Ok(data) => Ok(Some(data)),
+            Err(e @ DecodeError::Corrupt(_)) => {
+                self.quarantine(user_id, &e)?;
+                Err(io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    fn commit(&self, writes: &[(&str, &UserData)], entries: &mut [LedgerEntry]) -> io::Result<()> {
+        // Nothing is journaled unless every write has a safe path.
+        if let Some((user_id, _)) = writes.iter().find(|(user_id, _)| !is_valid_user_id(user_id)) {
+            return Err(invalid_user_id(user_id));
+        }
+        let mut last_entry_id = self.last_entry_id.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.journal_path().exists() {
+            // A previous commit failed half-way; finish it before starting another.
+            self.replay_journal()?;
+            *last_entry_id = None;
+        }
+        let mut next_id = match *last_entry_id {
+            Some(id) => id,
+            None => self.read_last_entry_id()?,
+        };
+        for entry in entries.iter_mut() {
+            next_id += 1;
+            entry.id = next_id;
+        }
+
+        let journal = JournalRef { writes, entries: &*entries };
+        let journal = serde_json::to_vec(&journal).map_err(to_io_error)?;
+        write_atomically(&self.journal_path(), &journal)?;
+        let applied = self.append_entries(&entries.iter().collect::<Vec<_>>())
+            .and_then(|_| writes.iter().try_for_each(|(user_id, data)| self.write_user(user_id, data)));
+        if let Err(e) = applied {
+            // Part of the batch may be on disk; finish it from the journal.
+            *last_entry_id = None;
+            if let Err(replay_error) = self.replay_journal() {
+                eprintln!("🛑 Storage journal replay failed, retrying on next access: {:?}", replay_error);
+                return Err(io::Error::other(CommitPending(e)));
+            }
+            return Ok(());
+        }
+        *last_entry_id = Some(next_id);
+        if let Err(e) = fs::remove_file(self.journal_path()) {
+            // Everything is applied; replaying the journal again changes nothing.
+            eprintln!("🛑 Failed to remove storage journal: {:?}", e);
+        }
+        Ok(())
+    }
+
+    fn ledger(&self, user_id: Option<&str>) -> io::Result<Vec<LedgerEntry>> {
+        self.finish_pending_commit()?;
+        let Some(user_id) = user_id else {
+            return self.read_ledger();
+        };
+        let ledger_index = self.ledger_index()?;
+        match ledger_index.as_ref().and_then(|index| index.lines.get(user_id)) {
+            Some(lines) => self.read_entries(lines.iter()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn ledger_page(&self, user_id: &str, offset: usize, limit: usize) -> io::Result<(Vec<LedgerEntry>, usize)> {
+        self.finish_pending_commit()?;
+        let ledger_index = self.ledger_index()?;
+        let Some(lines) = ledger_index.as_ref().and_then(|index| index.lines.get(user_id)) else {
+            return Ok((Vec::new(), 0));
+        };
+        let page = self.read_entries(lines.iter().rev().skip(offset).take(limit))?;
+        Ok((page, lines.len()))
+    }
+
+    fn pact_entry(&self, server_seed_hash: &str, nonce: u64) -> io::Result<Option<LedgerEntry>> {
+        self.finish_pending_commit()?;
+        let ledger_index = self.ledger_index()?;
+        let key = (server_seed_hash.to_string(), nonce);
+        match ledger_index.as_ref().and_then(|index| index.proofs.get(&key)) {
+            Some(range) => Ok(self.read_entries(std::iter::once(range))?.pop()),
+            None => Ok(None),
+        }
+    }
+
+    fn top_balances(&self, limit: usize, offset: usize) -> io::Result<(Vec<(String, Money)>, usize)> {
+        self.finish_pending_commit()?;
+        let balance_index = self.balance_index()?;
+        let index = balance_index.as_ref().expect("balance index is built");
+        let page = index.ranking
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(Reverse(balance), user_id)| (user_id.clone(), *balance))
+            .collect();
+        Ok((page, index.balances.len()))
+    }
+
+    fn rank(&self, user_id: &str) -> io::Result<Option<usize>> {
+        self.finish_pending_commit()?;
+        let balance_index = self.balance_index()?;
+        Ok(balance_index.as_ref().and_then(|index| index.rank(user_id)))
+    }
+}
+
+/// Embedded SQLite database. The full record is kept as JSON next to an
+/// indexed `balance` column so ranking queries do not need to decode rows.
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let conn = Connection::open(path).map_err(to_io_error)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS users (
+                 user_id TEXT PRIMARY KEY,
+                 balance INTEGER NOT NULL,
+                 data    TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS users_rank_idx ON users (balance DESC, user_id);
+             CREATE TABLE IF NOT EXISTS ledger (
+                 id      INTEGER PRIMARY KEY,
+                 user_id TEXT NOT NULL,
+                 data    TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS ledger_user_idx ON ledger (user_id, id);
+             CREATE INDEX IF NOT EXISTS ledger_proof_idx ON ledger (
+                 json_extract(data, '$.proof.server_seed_hash'),
+                 json_extract(data, '$.proof.nonce')
+             );
+             CREATE TABLE IF NOT EXISTS quarantine (
+                 user_id        TEXT NOT NULL,
+                 quarantined_at INTEGER NOT NULL,
+                 reason         TEXT NOT NULL,
+                 data           TEXT NOT NULL
+             );",
+        )
+        .map_err(to_io_error)?;
+        Ok(SqliteStorage { conn: Mutex::new(conn) })
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
+            .map_err(to_io_error)?;
+        Ok(count == 0)
+    }
+
+    /// One-shot copy of every user file and the ledger from a JSON data
+    /// directory, done in a single transaction so a failed import leaves the
+    /// database empty. Ledger entries keep their ids.
+    pub fn import_json(&self, json: &JsonStorage) -> io::Result<usize> {
+        let mut records = Vec::new();
+        for user_id in json.user_ids()? {
+            if let Some(data) = json.load(&user_id)? {
+                records.push((user_id, data));
+            }
+        }
+        let entries = json.ledger(None)?;
+        let mut conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let tx = conn.transaction().map_err(to_io_error)?;
+        for (user_id, data) in &records {
+            upsert_user(&tx, user_id, data)?;
+        }
+        for entry in &entries {
+            insert_entry(&tx, entry)?;
+        }
+        tx.commit().map_err(to_io_error)?;
+        Ok(records.len())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn exists(&self, user_id: &str) -> io::Result<bool> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let found = conn
+            .query_row("SELECT 1 FROM users WHERE user_id = ?1", params![user_id], |_| Ok(()))
+            .optional()
+            .map_err(to_io_error)?;
+        Ok(found.is_some())
+    }
+
+    fn load(&self, user_id: &str) -> io::Result<Option<UserData>> {
+        let mut conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let data_str: Option<String> = conn
+            .query_row("SELECT data FROM users WHERE user_id = ?1", params![user_id], |row| row.get(0))
+            .optional()
+            .map_err(to_io_error)?;
+        let Some(data_str) = data_str else {
+            return Ok(None);
+        };
+        match migrations::decode_user_data(&data_str) {
+            Ok(data) => Ok(Some(data)),
+            Err(e @ DecodeError::Corrupt(_)) => {
+                let tx = conn.transaction().map_err(to_io_error)?;
+                tx.execute(
+                    "INSERT INTO quarantine (user_id, quarantined_at, reason, data) VALUES (?1, ?2, ?3, ?4)",
+                    params![user_id, current_unix_time(), e.to_string(), data_str],
+                )
+                .map_err(to_io_error)?;
+                tx.execute("DELETE FROM users WHERE user_id = ?1", params![user_id])
+                    .map_err(to_io_error)?;
+                tx.commit().map_err(to_io_error)?;
+                eprintln!("🛑 Quarantined data of user {}: {}", user_id, e);
+                Err(io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    fn commit(&self, writes: &[(&str, &UserData)], entries: &mut [LedgerEntry]) -> io::Result<()> {
+        let mut conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let tx = conn.transaction().map_err(to_io_error)?;
+        for (user_id, data) in writes {
+            upsert_user(&tx, user_id, data)?;
+        }
+        let mut next_id: u64 = tx
+            .query_row("SELECT COALESCE(MAX(id), 0) FROM ledger", [], |row| row.get(0))
+            .map_err(to_io_error)?;
+        for entry in entries.iter_mut() {
+            next_id += 1;
+            entry.id = next_id;
+            insert_entry(&tx, entry)?;
+        }
+        tx.commit().map_err(to_io_error)
+    }
+
+    fn ledger(&self, user_id: Option<&str>) -> io::Result<Vec<LedgerEntry>> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut stmt = conn
+            .prepare("SELECT data FROM ledger WHERE ?1 IS NULL OR user_id = ?1 ORDER BY id")
+            .map_err(to_io_error)?;
+        let rows = stmt
+            .query_map(params![user_id], |row| row.get::<_, String>(0))
+            .map_err(to_io_error)?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let data_str = row.map_err(to_io_error)?;
+            entries.push(serde_json::from_str(&data_str).map_err(to_io_error)?);
+        }
+        Ok(entries)
+    }
+
+    fn ledger_page(&self, user_id: &str, offset: usize, limit: usize) -> io::Result<(Vec<LedgerEntry>, usize)> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let total: usize = conn
+            .query_row("SELECT COUNT(*) FROM ledger WHERE user_id = ?1", params![user_id], |row| row.get(0))
+            .map_err(to_io_error)?;
+        let mut stmt = conn
+            .prepare("SELECT data FROM ledger WHERE user_id = ?1 ORDER BY id DESC LIMIT ?2 OFFSET ?3")
+            .map_err(to_io_error)?;
+        let rows = stmt
+            .query_map(params![user_id, limit, offset], |row| row.get::<_, String>(0))
+            .map_err(to_io_error)?;
+        let mut page = Vec::new();
+        for row in rows {
+            let data_str = row.map_err(to_io_error)?;
+            page.push(serde_json::from_str(&data_str).map_err(to_io_error)?);
+        }
+        Ok((page, total))
+    }
+
+    fn pact_entry(&self, server_seed_hash: &str, nonce: u64) -> io::Result<Option<LedgerEntry>> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let data: Option<String> = conn
+            .query_row(
+                "SELECT data FROM ledger
+                 WHERE json_extract(data, '$.proof.server_seed_hash') = ?1
+                   AND json_extract(data, '$.proof.nonce') = ?2",
+                params![server_seed_hash, nonce],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(to_io_error)?;
+        data.map(|data| serde_json::from_str(&data).map_err(to_io_error)).transpose()
+    }
+
+    fn top_balances(&self, limit: usize, offset: usize) -> io::Result<(Vec<(String, Money)>, usize)> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let total: usize = conn
+            .query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
+            .map_err(to_io_error)?;
+        let mut stmt = conn
+            .prepare("SELECT user_id, balance FROM users ORDER BY balance DESC, user_id LIMIT ?1 OFFSET ?2")
+            .map_err(to_io_error)?;
+        let rows = stmt
+            .query_map(params![limit, offset], |row| {
+                Ok((row.get::<_, String>(0)?, Money::from_minor(row.get(1)?)))
+            })
+            .map_err(to_io_error)?;
+        let page = rows.collect::<Result<Vec<_>, _>>().map_err(to_io_error)?;
+        Ok((page, total))
+    }
+
+    fn rank(&self, user_id: &str) -> io::Result<Option<usize>> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let balance: Option<i64> = conn
+            .query_row("SELECT balance FROM users WHERE user_id = ?1", params![user_id], |row| row.get(0))
+            .optional()
+            .map_err(to_io_error)?;
+        let Some(balance) = balance else {
+            return Ok(None);
+        };
+        let ahead: usize = conn
+            .query_row(
+                "SELECT COUNT(*) FROM users WHERE balance > ?1 OR (balance = ?1 AND user_id < ?2)",
+                params![balance, user_id],
+                |row| row.get(0),
+            )
+            .map_err(to_io_error)?;
+        Ok(Some(ahead + 1))
+    }
+}
+
+fn upsert_user(conn: &Connection, user_id: &str, data: &UserData) -> io::Result<()> {
+    let json_data = serde_json::to_string(data).map_err(to_io_error)?;
+    conn.execute(
+        "INSERT INTO users (user_id, balance, data) VALUES (?1, ?2, ?3)
+         ON CONFLICT(user_id) DO UPDATE SET balance = excluded.balance, data = excluded.data",
+        params![user_id, data.balance.minor(), json_data],
+    )
+    .map_err(to_io_error)?;
+    Ok(())
+}
+
+fn insert_entry(conn: &Connection, entry: &LedgerEntry) -> io::Result<()> {
+    let json_data = serde_json::to_string(entry).map_err(to_io_error)?;
+    conn.execute(
+        "INSERT INTO ledger (id, user_id, data) VALUES (?1, ?2, ?3)",
+        params![entry.id, entry.user_id, json_data],
+    )
+    .map_err(to_io_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fairness::PactProof;
+    use crate::ledger::EntryKind;
+
+    /// A fresh scratch directory, so tests never touch `./data`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("arcwarden-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("🛑 Error creating temp directory");
+        dir
+    }
+
+    fn user(minor: i64) -> UserData {
+        UserData { balance: Money::from_minor(minor), ledger_started: true, ..UserData::default() }
+    }
+
+    fn check_backend(storage: &dyn Storage) {
+        assert!(!storage.exists("1").unwrap());
+        assert!(storage.load("1").unwrap().is_none());
+
+        let mut entries = vec![
+            LedgerEntry::new("1", EntryKind::Opening, Money::from_minor(300), Money::from_minor(300), "9"),
+            LedgerEntry::new("2", EntryKind::Opening, Money::from_minor(500), Money::from_minor(500), "9"),
+        ];
+        storage.commit(&[("1", &user(300)), ("2", &user(500))], &mut entries).unwrap();
+        assert_eq!((entries[0].id, entries[1].id), (1, 2));
+        assert!(storage.exists("1").unwrap());
+        assert_eq!(storage.load("2").unwrap().unwrap().balance, Money::from_minor(500));
+        assert_eq!(storage.ledger(None).unwrap().len(), 2);
+        assert_eq!(storage.ledger(Some("1")).unwrap()[0].amount, Money::from_minor(300));
+
+        let (top, total) = storage.top_balances(1, 0).unwrap();
+        assert_eq!(top, vec![("2".to_string(), Money::from_minor(500))]);
+        assert_eq!(total, 2);
+        assert_eq!(storage.rank("1").unwrap(), Some(2));
+        assert_eq!(storage.rank("3").unwrap(), None);
+
+        let proof = PactProof {
+            server_seed_hash: "hash".to_string(),
+            client_seed: "client".to_string(),
+            nonce: 3,
+            win_chance: 0.45,
+            min_percent: 7,
+            max_percent: 32,
+        };
+        let mut entries = vec![
+            LedgerEntry::new("1", EntryKind::PactWin, Money::from_minor(10), Money::from_minor(310), "1")
+                .with_proof(Some(proof)),
+        ];
+        storage.commit(&[("1", &user(310))], &mut entries).unwrap();
+        assert_eq!(storage.pact_entry("hash", 3).unwrap().unwrap().id, 3);
+        assert!(storage.pact_entry("hash", 4).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_json_backend() {
+        check_backend(&JsonStorage::new(temp_dir("json-backend")));
+    }
+
+    #[test]
+    fn test_sqlite_backend() {
+        check_backend(&SqliteStorage::open(":memory:").unwrap());
+    }
+
+    #[test]
+    fn test_sqlite_imports_json_users() {
+        let json = JsonStorage::new(temp_dir("json-import"));
+        let mut entries = vec![
+            LedgerEntry::new("1", EntryKind::Opening, Money::from_minor(300), Money::from_minor(300), "9"),
+            LedgerEntry::new("2", EntryKind::Opening, Money::from_minor(500), Money::from_minor(500), "9"),
+        ];
+        json.commit(&[("1", &user(300)), ("2", &user(500))], &mut entries).unwrap();
+
+        let sqlite = SqliteStorage::open(":memory:").unwrap();
+        assert!(sqlite.is_empty().unwrap());
+        assert_eq!(sqlite.import_json(&json).unwrap(), 2);
+        assert!(!sqlite.is_empty().unwrap());
+        assert_eq!(sqlite.load("1").unwrap().unwrap().balance, Money::from_minor(300));
+        let ids: Vec<u64> = sqlite.ledger(None).unwrap().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_rejects_path_traversal() {
+        let dir = temp_dir("traversal");
+        let storage = JsonStorage::new(dir.join("users"));
+        fs::create_dir_all(dir.join("users")).unwrap();
+        fs::write(dir.join("secret.json"), "{}").unwrap();
+
+        for user_id in ["../secret", "..", "1/../2", "", "+1", "1.5"] {
+            assert_eq!(storage.exists(user_id).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+            assert!(storage.load(user_id).is_err_and(|err| err.kind() == io::ErrorKind::InvalidInput));
+            let err = storage.commit(&[(user_id, &user(1))], &mut []).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(!dir.join("users").join(JOURNAL_FILE).exists());
+        assert_eq!(fs::read_to_string(dir.join("secret.json")).unwrap(), "{}");
+        assert!(storage.exists("123456789012345678").is_ok());
+    }
+
+    #[test]
+    fn test_quarantines_corrupt_record() {
+        let dir = temp_dir("quarantine");
+        let storage = JsonStorage::new(&dir);
+        fs::write(dir.join("7.json"), "{\"balance\":").unwrap();
+
+        assert!(storage.load("7").is_err_and(|err| err.kind() == io::ErrorKind::InvalidData));
+        assert!(!storage.exists("7").unwrap());
+        assert_eq!(fs::read_dir(dir.join(QUARANTINE_DIRECTORY)).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_replays_journal_after_crash() {
+        let dir = temp_dir("journal-replay");
+        let storage = JsonStorage::new(&dir);
+        let mut entries = vec![LedgerEntry::new("1", EntryKind::Opening, Money::from_minor(500), Money::from_minor(500), "9")];
+        storage.commit(&[("1", &user(500))], &mut entries).unwrap();
+
+        // A transfer that crashed after its journal and the sender's ledger line were written.
+        let mut transfer = vec![
+            LedgerEntry::new("1", EntryKind::PaymentSent, Money::from_minor(-200), Money::from_minor(300), "1"),
+            LedgerEntry::new("2", EntryKind::PaymentReceived, Money::from_minor(200), Money::from_minor(200), "1"),
+        ];
+        transfer[0].id = 2;
+        transfer[1].id = 3;
+        let (sender, recipient) = (user(300), user(200));
+        let journal = JournalRef { writes: &[("1", &sender), ("2", &recipient)], entries: &transfer };
+        fs::write(dir.join(JOURNAL_FILE), serde_json::to_vec(&journal).unwrap()).unwrap();
+        storage.append_entries(&[&transfer[0]]).unwrap();
+
+        let storage = JsonStorage::new(&dir);
+        storage.recover().unwrap();
+        assert!(!dir.join(JOURNAL_FILE).exists());
+        assert_eq!(storage.load("1").unwrap().unwrap().balance, Money::from_minor(300));
+        assert_eq!(storage.load("2").unwrap().unwrap().balance, Money::from_minor(200));
+        let ids: Vec<u64> = storage.ledger(None).unwrap().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    fn check_ledger_paging(storage: &dyn Storage) {
+        for i in 1..=25 {
+            let mut entries = vec![LedgerEntry::new("1", EntryKind::Opening, Money::from_minor(i), Money::from_minor(i), "1")];
+            if i % 10 == 0 {
+                entries.push(LedgerEntry::new("2", EntryKind::Opening, Money::from_minor(i), Money::from_minor(i), "2"));
+            }
+            storage.commit(&[], &mut entries).unwrap();
+        }
+        let (page, total) = storage.ledger_page("1", 0, 10).unwrap();
+        assert_eq!(total, 25);
+        let amounts: Vec<i64> = page.iter().map(|entry| entry.amount.minor()).collect();
+        assert_eq!(amounts, (16..=25).rev().collect::<Vec<_>>());
+        let (page, _) = storage.ledger_page("1", 20, 10).unwrap();
+        assert_eq!(page.len(), 5);
+        assert_eq!(page[4].amount, Money::from_minor(1));
+        let (page, total) = storage.ledger_page("2", 0, 10).unwrap();
+        assert_eq!((page.len(), total), (2, 2));
+        assert_eq!(storage.ledger_page("3", 0, 10).unwrap().1, 0);
+    }
+
+    #[test]
+    fn test_ledger_paging() {
+        let dir = temp_dir("ledger-paging");
+        check_ledger_paging(&JsonStorage::new(&dir));
+        // A fresh index built from the file gives the same pages.
+        let reopened = JsonStorage::new(&dir);
+        let (page, total) = reopened.ledger_page("1", 0, 3).unwrap();
+        assert_eq!(total, 25);
+        assert_eq!(page[0].id, 27);
+        check_ledger_paging(&SqliteStorage::open(":memory:").unwrap());
+    }
+}
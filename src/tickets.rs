@@ -0,0 +1,783 @@
+use serde::{Serialize, Deserialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use crate::balance::{current_unix_time, DATA_DIRECTORY};
+use crate::config::TicketLimitsConfig;
+use crate::storage;
+
+/// The registry sits in a subdirectory of the data directory, where user
+/// files are not listed.
+const TICKETS_DIRECTORY: &str = "tickets";
+const TICKETS_FILE: &str = "registry.json";
+const FIRST_TICKET_ID: u32 = 10000;
+
+static TICKETS: OnceLock<Mutex<TicketStore>> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TicketStatus {
+    Open,
+    /// Soft-closed: the channel is kept so the ticket can be reopened.
+    Archived,
+    Closed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    /// Closed by a moderator.
+    Manual,
+    /// Closed by the inactivity sweep after its warning went unanswered.
+    Inactivity,
+    /// Its channel was deleted outside the bot.
+    ChannelDeleted,
+}
+
+impl CloseReason {
+    pub fn label(self) -> &'static str {
+        match self {
+            CloseReason::Manual => "closed by a moderator",
+            CloseReason::Inactivity => "closed for inactivity",
+            CloseReason::ChannelDeleted => "closed because its channel was deleted",
+        }
+    }
+}
+
+/// Message activity newer than this is not written back to disk again.
+const ACTIVITY_RESOLUTION_SECS: u64 = 60;
+
+/// The opener's verdict on a closed ticket.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TicketRating {
+    /// 1 to 5.
+    pub stars: u8,
+    /// Moderator who handled the ticket when it was rated.
+    pub moderator_id: Option<u64>,
+    #[serde(default)]
+    pub feedback: Option<String>,
+    pub rated_at: u64,
+}
+
+/// A private moderator note; it only appears in the staff transcript.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TicketNote {
+    pub author_id: u64,
+    pub created_at: u64,
+    pub text: String,
+}
+
+/// An answer from the intake form, kept with the question as it was asked.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IntakeAnswer {
+    pub question: String,
+    pub answer: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ticket {
+    pub id: u32,
+    pub channel_id: u64,
+    pub opener_id: u64,
+    /// Category the channel was created in, used to put it back on reopen.
+    #[serde(default)]
+    pub category_id: Option<u64>,
+    pub created_at: u64,
+    pub closed_at: Option<u64>,
+    pub status: TicketStatus,
+    pub assigned_to: Option<u64>,
+    /// Name of the configured ticket type; `None` for tickets opened before types existed.
+    #[serde(default)]
+    pub ticket_type: Option<String>,
+    #[serde(default)]
+    pub intake: Vec<IntakeAnswer>,
+    /// Time of the last message from a member; `created_at` until someone writes.
+    #[serde(default)]
+    pub last_activity: Option<u64>,
+    /// When the inactivity warning was posted, cleared by new activity.
+    #[serde(default)]
+    pub inactivity_warned_at: Option<u64>,
+    #[serde(default)]
+    pub close_reason: Option<CloseReason>,
+    /// First message from staff other than the opener.
+    #[serde(default)]
+    pub first_response_at: Option<u64>,
+    #[serde(default)]
+    pub first_responder: Option<u64>,
+    #[serde(default)]
+    pub rating: Option<TicketRating>,
+    #[serde(default)]
+    pub notes: Vec<TicketNote>,
+    /// Lowercase labels such as "refund" or "duplicate".
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Ticket {
+    pub fn last_activity(&self) -> u64 {
+        self.last_activity.unwrap_or(self.created_at)
+    }
+}
+
+/// A ticket id handed out whose channel is still being created. It counts
+/// against the opener's limits until `record_open` or `release_id`.
+struct Reservation {
+    id: u32,
+    opener_id: u64,
+    reserved_at: u64,
+}
+
+/// Every ticket ever opened plus the next id to hand out. Ids are persisted
+/// before a channel is created, so they are never reused across restarts.
+#[derive(Serialize, Deserialize)]
+struct TicketStore {
+    /// Where the registry was loaded from and is saved back to.
+    #[serde(skip)]
+    path: PathBuf,
+    next_id: u32,
+    tickets: Vec<Ticket>,
+    #[serde(skip)]
+    reservations: Vec<Reservation>,
+}
+
+impl Default for TicketStore {
+    fn default() -> Self {
+        TicketStore {
+            path: PathBuf::new(),
+            next_id: FIRST_TICKET_ID,
+            tickets: Vec::new(),
+            reservations: Vec::new(),
+        }
+    }
+}
+
+impl TicketStore {
+    fn load(directory: &Path) -> io::Result<Self> {
+        let path = directory.join(TICKETS_DIRECTORY).join(TICKETS_FILE);
+        let mut store: TicketStore = storage::load_json_file(&path)?;
+        store.path = path;
+        Ok(store)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        storage::save_json_file(&self.path, self)
+    }
+
+    /// Why `opener_id` may not open another ticket at `now`, if anything.
+    /// Reserved ids count as open tickets created when they were reserved.
+    fn refusal_for(&self, opener_id: u64, limits: &TicketLimitsConfig, now: u64) -> Option<String> {
+        let opened: Vec<&Ticket> = self.tickets.iter().filter(|ticket| ticket.opener_id == opener_id).collect();
+        let open: Vec<&&Ticket> = opened.iter().filter(|ticket| ticket.status == TicketStatus::Open).collect();
+        let reserved: Vec<&Reservation> = self.reservations.iter().filter(|reservation| reservation.opener_id == opener_id).collect();
+        if limits.max_open_per_user > 0 && open.len() + reserved.len() >= limits.max_open_per_user {
+            if open.is_empty() {
+                return Some("🛑 Your ticket is still being created, please wait a moment.".to_string());
+            }
+            let channels: Vec<String> = open.iter().map(|ticket| format!("<#{}>", ticket.channel_id)).collect();
+            return Some(format!("🛑 You already have an open ticket: {}", channels.join(", ")));
+        }
+        let last_created = opened.iter().map(|ticket| ticket.created_at)
+            .chain(reserved.iter().map(|reservation| reservation.reserved_at))
+            .max()?;
+        let available_at = last_created + limits.creation_cooldown_secs;
+        if now < available_at {
+            return Some(format!("🛑 You opened a ticket recently. You can open another one <t:{}:R>.", available_at));
+        }
+        None
+    }
+}
+
+/// Loads the ticket registry kept under `directory`. An unreadable file is
+/// an error rather than an empty registry, so ids can never be handed out twice.
+pub fn init(directory: &Path) -> io::Result<()> {
+    let store = TicketStore::load(directory)?;
+    TICKETS
+        .set(Mutex::new(store))
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "ticket store already initialized"))
+}
+
+fn store() -> MutexGuard<'static, TicketStore> {
+    TICKETS
+        .get_or_init(|| Mutex::new(TicketStore::load(Path::new(DATA_DIRECTORY)).expect("🛑 Failed to load ticket store")))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Checks the opener's limits and reserves the next ticket id under one lock,
+/// so two requests at once cannot both pass the check. The id is persisted
+/// immediately and counts against the opener until `record_open` or
+/// `release_id`. Returns the message to show when the opener is refused.
+pub fn reserve_id(opener_id: u64, limits: &TicketLimitsConfig) -> Result<u32, String> {
+    let now = current_unix_time();
+    let mut store = store();
+    if let Some(refusal) = store.refusal_for(opener_id, limits, now) {
+        return Err(refusal);
+    }
+    let id = store.next_id;
+    store.next_id += 1;
+    if let Err(e) = store.save() {
+        store.next_id = id;
+        eprintln!("🛑 Failed to reserve a ticket id: {:?}", e);
+        return Err("🛑 Could not create ticket, please try again later.".to_string());
+    }
+    store.reservations.push(Reservation { id, opener_id, reserved_at: now });
+    Ok(id)
+}
+
+/// Gives up a reserved id whose channel could not be created. The id itself
+/// stays used.
+pub fn release_id(id: u32) {
+    store().reservations.retain(|reservation| reservation.id != id);
+}
+
+pub fn record_open(
+    id: u32,
+    channel_id: u64,
+    category_id: u64,
+    opener_id: u64,
+    ticket_type: &str,
+    intake: Vec<IntakeAnswer>,
+) -> io::Result<Ticket> {
+    let ticket = Ticket {
+        id,
+        channel_id,
+        opener_id,
+        category_id: Some(category_id),
+        created_at: current_unix_time(),
+        closed_at: None,
+        status: TicketStatus::Open,
+        assigned_to: None,
+        ticket_type: Some(ticket_type.to_string()),
+        intake,
+        last_activity: None,
+        inactivity_warned_at: None,
+        close_reason: None,
+        first_response_at: None,
+        first_responder: None,
+        rating: None,
+        notes: Vec::new(),
+        tags: Vec::new(),
+    };
+    let mut store = store();
+    store.reservations.retain(|reservation| reservation.id != id);
+    store.tickets.push(ticket.clone());
+    store.save()?;
+    Ok(ticket)
+}
+
+pub fn find_by_channel(channel_id: u64) -> Option<Ticket> {
+    store().tickets.iter().find(|ticket| ticket.channel_id == channel_id).cloned()
+}
+
+/// Applies `change` to the ticket of `channel_id` and persists the result.
+pub fn update<F: FnOnce(&mut Ticket)>(channel_id: u64, change: F) -> io::Result<Option<Ticket>> {
+    let mut store = store();
+    let Some(index) = store.tickets.iter().position(|ticket| ticket.channel_id == channel_id) else {
+        return Ok(None);
+    };
+    let previous = store.tickets[index].clone();
+    change(&mut store.tickets[index]);
+    let updated = store.tickets[index].clone();
+    if let Err(e) = store.save() {
+        store.tickets[index] = previous;
+        return Err(e);
+    }
+    Ok(Some(updated))
+}
+
+/// Why `opener_id` may not open another ticket right now, if anything. This
+/// only answers early, e.g. before showing an intake form; `reserve_id` checks
+/// again when the ticket is created.
+pub fn open_refusal(opener_id: u64, limits: &TicketLimitsConfig) -> Option<String> {
+    store().refusal_for(opener_id, limits, current_unix_time())
+}
+
+pub fn open_tickets() -> Vec<Ticket> {
+    store().tickets.iter().filter(|ticket| ticket.status == TicketStatus::Open).cloned().collect()
+}
+
+/// Records a message in an open ticket and withdraws any inactivity warning.
+pub fn record_activity(channel_id: u64) -> io::Result<()> {
+    let now = current_unix_time();
+    let mut store = store();
+    let Some(index) = store.tickets.iter()
+        .position(|ticket| ticket.channel_id == channel_id && ticket.status == TicketStatus::Open)
+    else {
+        return Ok(());
+    };
+    let ticket = &mut store.tickets[index];
+    if ticket.inactivity_warned_at.is_none() && now.saturating_sub(ticket.last_activity()) < ACTIVITY_RESOLUTION_SECS {
+        return Ok(());
+    }
+    ticket.last_activity = Some(now);
+    ticket.inactivity_warned_at = None;
+    store.save()
+}
+
+/// Stores the opener's 1 to 5 star rating of a closed or archived ticket,
+/// credited to the moderator who handled it: the assignee, or else whoever
+/// replied first. A ticket is rated once; the error is shown to the rater.
+pub fn record_rating(ticket_id: u32, rater_id: u64, stars: u8) -> Result<TicketRating, String> {
+    let mut store = store();
+    let Some(index) = store.tickets.iter().position(|ticket| ticket.id == ticket_id) else {
+        return Err("🛑 This ticket no longer exists.".to_string());
+    };
+    let ticket = &store.tickets[index];
+    if ticket.opener_id != rater_id {
+        return Err("🛑 Only the opener can rate this ticket.".to_string());
+    }
+    if ticket.status == TicketStatus::Open {
+        return Err("🛑 This ticket is open again.".to_string());
+    }
+    if ticket.rating.is_some() {
+        return Err("🛑 You have already rated this ticket.".to_string());
+    }
+    if !(1..=5).contains(&stars) {
+        return Err("🛑 Ratings go from 1 to 5 stars.".to_string());
+    }
+    let rating = TicketRating {
+        stars,
+        moderator_id: ticket.assigned_to.or(ticket.first_responder),
+        feedback: None,
+        rated_at: current_unix_time(),
+    };
+    store.tickets[index].rating = Some(rating.clone());
+    if let Err(e) = store.save() {
+        eprintln!("🛑 Failed to save rating of ticket #{}: {:?}", ticket_id, e);
+        store.tickets[index].rating = None;
+        return Err("🛑 Could not save your rating, please try again later.".to_string());
+    }
+    Ok(rating)
+}
+
+/// Attaches written feedback to a rating the opener already gave.
+pub fn record_feedback(ticket_id: u32, rater_id: u64, feedback: &str) -> String {
+    let mut store = store();
+    let Some(ticket) = store.tickets.iter_mut().find(|ticket| ticket.id == ticket_id) else {
+        return "🛑 This ticket no longer exists.".to_string();
+    };
+    if ticket.opener_id != rater_id {
+        return "🛑 Only the opener can leave feedback on this ticket.".to_string();
+    }
+    let Some(rating) = ticket.rating.as_mut() else {
+        return "🛑 Please rate the ticket first.".to_string();
+    };
+    rating.feedback = Some(feedback.trim().to_string()).filter(|feedback| !feedback.is_empty());
+    if let Err(e) = store.save() {
+        eprintln!("🛑 Failed to save feedback of ticket #{}: {:?}", ticket_id, e);
+        return "🛑 Could not save your feedback, please try again later.".to_string();
+    }
+    "✅ Thank you for your feedback!".to_string()
+}
+
+/// Average rating per moderator over every rated ticket.
+pub fn rating_summary() -> String {
+    let store = store();
+    let mut ratings: BTreeMap<Option<u64>, Vec<u8>> = BTreeMap::new();
+    for rating in store.tickets.iter().filter_map(|ticket| ticket.rating.as_ref()) {
+        ratings.entry(rating.moderator_id).or_default().push(rating.stars);
+    }
+    if ratings.is_empty() {
+        return "⭐ No tickets have been rated yet.".to_string();
+    }
+    let mut averages: Vec<(Option<u64>, f64, usize)> = ratings
+        .into_iter()
+        .map(|(moderator, stars)| {
+            let average = stars.iter().map(|&stars| stars as f64).sum::<f64>() / stars.len() as f64;
+            (moderator, average, stars.len())
+        })
+        .collect();
+    averages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut lines = vec!["⭐ Ticket ratings per moderator".to_string()];
+    for (moderator, average, count) in averages {
+        let moderator = moderator.map_or("Unassigned".to_string(), |id| format!("<@{}>", id));
+        lines.push(format!("{}: {:.2} from {} rating(s)", moderator, average, count));
+    }
+    lines.join("\n")
+}
+
+pub fn add_note(channel_id: u64, author_id: u64, text: &str) -> io::Result<Option<Ticket>> {
+    let note = TicketNote {
+        author_id,
+        created_at: current_unix_time(),
+        text: text.trim().to_string(),
+    };
+    update(channel_id, |ticket| ticket.notes.push(note))
+}
+
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().trim_start_matches('#').to_lowercase()
+}
+
+/// Adds or removes a tag; returns whether the ticket's tags changed.
+pub fn set_tag(channel_id: u64, tag: &str, present: bool) -> io::Result<bool> {
+    let tag = normalize_tag(tag);
+    let mut changed = false;
+    update(channel_id, |ticket| {
+        let has_tag = ticket.tags.contains(&tag);
+        if present && !has_tag {
+            ticket.tags.push(tag.clone());
+            changed = true;
+        } else if !present && has_tag {
+            ticket.tags.retain(|existing| existing != &tag);
+            changed = true;
+        }
+    })?;
+    Ok(changed)
+}
+
+/// Closed and archived tickets carrying `tag`, most recently closed first.
+pub fn search_by_tag(tag: &str) -> Vec<Ticket> {
+    tagged(&store().tickets, tag)
+}
+
+fn tagged(tickets: &[Ticket], tag: &str) -> Vec<Ticket> {
+    let tag = normalize_tag(tag);
+    let mut found: Vec<Ticket> = tickets
+        .iter()
+        .filter(|ticket| ticket.status != TicketStatus::Open && ticket.tags.contains(&tag))
+        .cloned()
+        .collect();
+    found.sort_by_key(|ticket| Reverse(ticket.closed_at));
+    found
+}
+
+pub fn search_summary(tag: &str) -> String {
+    const MAX_RESULTS: usize = 20;
+    let found = search_by_tag(tag);
+    if found.is_empty() {
+        return format!("🔎 No closed tickets are tagged `{}`.", normalize_tag(tag));
+    }
+    let mut lines = vec![format!("🔎 {} closed ticket(s) tagged `{}`", found.len(), normalize_tag(tag))];
+    for ticket in found.iter().take(MAX_RESULTS) {
+        let closed = ticket.closed_at.map_or(String::new(), |closed_at| format!(", closed <t:{}:d>", closed_at));
+        lines.push(format!(
+            "#{} ({}) opened by <@{}>{} [{}]",
+            ticket.id,
+            ticket.ticket_type.as_deref().unwrap_or("untyped"),
+            ticket.opener_id,
+            closed,
+            ticket.tags.join(", ")
+        ));
+    }
+    if found.len() > MAX_RESULTS {
+        lines.push(format!("…and {} more", found.len() - MAX_RESULTS));
+    }
+    lines.join("\n")
+}
+
+/// Records the first staff reply; later replies leave it untouched.
+pub fn record_first_response(channel_id: u64, responder_id: u64) -> io::Result<()> {
+    let now = current_unix_time();
+    let mut store = store();
+    let Some(ticket) = store.tickets.iter_mut().find(|ticket| ticket.channel_id == channel_id) else {
+        return Ok(());
+    };
+    if ticket.first_response_at.is_some() {
+        return Ok(());
+    }
+    ticket.first_response_at = Some(now);
+    ticket.first_responder = Some(responder_id);
+    store.save()
+}
+
+/// An archived ticket keeps the time and reason of its soft close.
+pub fn mark_closed(channel_id: u64, reason: CloseReason) -> io::Result<Option<Ticket>> {
+    update(channel_id, |ticket| {
+        ticket.status = TicketStatus::Closed;
+        ticket.closed_at.get_or_insert(current_unix_time());
+        // The bot's own delete can be reported back as a deleted channel before it records why.
+        if matches!(ticket.close_reason, None | Some(CloseReason::ChannelDeleted)) {
+            ticket.close_reason = Some(reason);
+        }
+    })
+}
+
+pub fn mark_archived(channel_id: u64, reason: CloseReason) -> io::Result<Option<Ticket>> {
+    update(channel_id, |ticket| {
+        ticket.status = TicketStatus::Archived;
+        ticket.closed_at = Some(current_unix_time());
+        ticket.close_reason = Some(reason);
+    })
+}
+
+pub fn mark_reopened(channel_id: u64) -> io::Result<Option<Ticket>> {
+    update(channel_id, |ticket| {
+        ticket.status = TicketStatus::Open;
+        ticket.closed_at = None;
+        ticket.close_reason = None;
+        ticket.last_activity = Some(current_unix_time());
+        ticket.inactivity_warned_at = None;
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsPeriod {
+    Day,
+    Week,
+    Month,
+    All,
+}
+
+impl StatsPeriod {
+    pub fn parse(period: &str) -> Option<StatsPeriod> {
+        match period {
+            "day" => Some(StatsPeriod::Day),
+            "week" => Some(StatsPeriod::Week),
+            "month" => Some(StatsPeriod::Month),
+            "all" => Some(StatsPeriod::All),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StatsPeriod::Day => "last 24 hours",
+            StatsPeriod::Week => "last 7 days",
+            StatsPeriod::Month => "last 30 days",
+            StatsPeriod::All => "all time",
+        }
+    }
+
+    /// Start of the period ending at `now`; `None` covers every ticket.
+    pub fn since(self, now: u64) -> Option<u64> {
+        let length = match self {
+            StatsPeriod::Day => 86400,
+            StatsPeriod::Week => 7 * 86400,
+            StatsPeriod::Month => 30 * 86400,
+            StatsPeriod::All => return None,
+        };
+        Some(now.saturating_sub(length))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModeratorStats {
+    pub moderator_id: String,
+    pub handled: usize,
+    pub median_first_response_secs: Option<u64>,
+}
+
+/// Queue statistics for tickets opened in a period. The backlog counts every
+/// ticket that is open right now, whenever it was opened.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TicketStats {
+    pub period: StatsPeriod,
+    pub opened: usize,
+    pub closed: usize,
+    pub median_first_response_secs: Option<u64>,
+    pub median_resolution_secs: Option<u64>,
+    pub per_moderator: Vec<ModeratorStats>,
+    pub backlog_by_type: BTreeMap<String, usize>,
+}
+
+fn median(mut values: Vec<u64>) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[middle - 1] + values[middle]) / 2)
+    } else {
+        Some(values[middle])
+    }
+}
+
+fn first_response_secs(ticket: &Ticket) -> Option<u64> {
+    ticket.first_response_at.map(|at| at.saturating_sub(ticket.created_at))
+}
+
+/// A ticket counts for the moderator it is assigned to, or else whoever answered first.
+fn compute_stats(tickets: &[Ticket], period: StatsPeriod, now: u64) -> TicketStats {
+    let since = period.since(now).unwrap_or(0);
+    let in_period: Vec<&Ticket> = tickets.iter().filter(|ticket| ticket.created_at >= since).collect();
+
+    let mut handled: BTreeMap<u64, Vec<&Ticket>> = BTreeMap::new();
+    for ticket in &in_period {
+        if let Some(moderator) = ticket.assigned_to.or(ticket.first_responder) {
+            handled.entry(moderator).or_default().push(ticket);
+        }
+    }
+    let mut per_moderator: Vec<ModeratorStats> = handled
+        .into_iter()
+        .map(|(moderator, tickets)| ModeratorStats {
+            moderator_id: moderator.to_string(),
+            handled: tickets.len(),
+            median_first_response_secs: median(tickets.iter().filter_map(|ticket| first_response_secs(ticket)).collect()),
+        })
+        .collect();
+    per_moderator.sort_by_key(|stats| Reverse(stats.handled));
+
+    let mut backlog_by_type = BTreeMap::new();
+    for ticket in tickets.iter().filter(|ticket| ticket.status == TicketStatus::Open) {
+        let ticket_type = ticket.ticket_type.clone().unwrap_or_else(|| "untyped".to_string());
+        *backlog_by_type.entry(ticket_type).or_insert(0) += 1;
+    }
+
+    TicketStats {
+        period,
+        opened: in_period.len(),
+        closed: in_period.iter().filter(|ticket| ticket.closed_at.is_some()).count(),
+        median_first_response_secs: median(in_period.iter().filter_map(|ticket| first_response_secs(ticket)).collect()),
+        median_resolution_secs: median(
+            in_period.iter()
+                .filter_map(|ticket| ticket.closed_at.map(|at| at.saturating_sub(ticket.created_at)))
+                .collect(),
+        ),
+        per_moderator,
+        backlog_by_type,
+    }
+}
+
+pub fn stats(period: StatsPeriod) -> TicketStats {
+    compute_stats(&store().tickets, period, current_unix_time())
+}
+
+fn format_duration(secs: Option<u64>) -> String {
+    match secs {
+        Some(secs) if secs >= 3600 => format!("{} h {} m", secs / 3600, (secs % 3600) / 60),
+        Some(secs) => format!("{} m {} s", secs / 60, secs % 60),
+        None => "n/a".to_string(),
+    }
+}
+
+pub fn stats_summary(period: StatsPeriod) -> String {
+    let stats = stats(period);
+    let mut lines = vec![
+        format!("📊 Ticket stats, {}", period.label()),
+        format!("Opened: {} | Closed: {}", stats.opened, stats.closed),
+        format!("Median first response: {}", format_duration(stats.median_first_response_secs)),
+        format!("Median time to close: {}", format_duration(stats.median_resolution_secs)),
+    ];
+    if !stats.per_moderator.is_empty() {
+        lines.push("Per moderator:".to_string());
+        for moderator in &stats.per_moderator {
+            lines.push(format!(
+                "  <@{}>: {} handled, median first response {}",
+                moderator.moderator_id,
+                moderator.handled,
+                format_duration(moderator.median_first_response_secs)
+            ));
+        }
+    }
+    let backlog: Vec<String> = stats.backlog_by_type.iter().map(|(ticket_type, open)| format!("{} {}", ticket_type, open)).collect();
+    lines.push(format!("Open backlog: {}", if backlog.is_empty() { "none".to_string() } else { backlog.join(", ") }));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn ticket(id: u32, created_at: u64, first_response_at: Option<u64>, closed_at: Option<u64>, moderator: Option<u64>) -> Ticket {
+        Ticket {
+            id,
+            channel_id: id as u64,
+            opener_id: 1,
+            category_id: None,
+            created_at,
+            closed_at,
+            status: if closed_at.is_some() { TicketStatus::Closed } else { TicketStatus::Open },
+            assigned_to: None,
+            ticket_type: Some("support".to_string()),
+            intake: Vec::new(),
+            last_activity: None,
+            inactivity_warned_at: None,
+            close_reason: None,
+            first_response_at,
+            first_responder: moderator,
+            rating: None,
+            notes: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        let now = 100 * 86400;
+        let tickets = vec![
+            ticket(1, now - 10 * 86400, Some(now - 10 * 86400 + 600), Some(now - 9 * 86400), Some(7)),
+            ticket(2, now - 3600, Some(now - 3000), None, Some(7)),
+            ticket(3, now - 1800, Some(now - 1600), Some(now - 600), Some(8)),
+            ticket(4, now - 60, None, None, None),
+        ];
+
+        let week = compute_stats(&tickets, StatsPeriod::Week, now);
+        assert_eq!(week.opened, 3);
+        assert_eq!(week.closed, 1);
+        assert_eq!(week.median_first_response_secs, Some(400));
+        assert_eq!(week.median_resolution_secs, Some(1200));
+        assert_eq!(week.per_moderator.len(), 2);
+        assert_eq!(week.backlog_by_type.get("support"), Some(&2));
+
+        let all = compute_stats(&tickets, StatsPeriod::All, now);
+        assert_eq!(all.opened, 4);
+        assert_eq!(all.per_moderator[0].moderator_id, "7");
+        assert_eq!(all.per_moderator[0].handled, 2);
+    }
+
+    #[test]
+    fn test_registry_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("arcwarden-tickets-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut store = TicketStore::load(&dir).unwrap();
+        assert_eq!(store.next_id, FIRST_TICKET_ID);
+        store.next_id += 1;
+        store.tickets.push(ticket(FIRST_TICKET_ID, 3, None, None, None));
+        store.save().unwrap();
+
+        let reloaded = TicketStore::load(&dir).unwrap();
+        assert_eq!(reloaded.next_id, FIRST_TICKET_ID + 1);
+        assert_eq!(reloaded.tickets[0].created_at, 3);
+
+        // A damaged registry must not restart ids from the beginning.
+        fs::write(&reloaded.path, "{").unwrap();
+        assert!(TicketStore::load(&dir).is_err());
+    }
+
+    #[test]
+    fn test_open_refusal() {
+        let limits = TicketLimitsConfig { max_open_per_user: 1, creation_cooldown_secs: 600 };
+        let mut store = TicketStore { tickets: vec![ticket(1, 1000, None, None, None)], ..TicketStore::default() };
+        assert!(store.refusal_for(1, &limits, 5000).unwrap().contains("<#1>"));
+        assert_eq!(store.refusal_for(2, &limits, 5000), None);
+
+        store.tickets[0].status = TicketStatus::Closed;
+        assert!(store.refusal_for(1, &limits, 1300).unwrap().contains("<t:1600:R>"));
+        assert_eq!(store.refusal_for(1, &limits, 1600), None);
+
+        // A ticket still being created counts like an open one.
+        store.reservations.push(Reservation { id: 2, opener_id: 1, reserved_at: 5000 });
+        assert!(store.refusal_for(1, &limits, 9000).is_some());
+        store.reservations.clear();
+
+        let unlimited = TicketLimitsConfig { max_open_per_user: 0, creation_cooldown_secs: 0 };
+        store.tickets[0].status = TicketStatus::Open;
+        assert_eq!(store.refusal_for(1, &unlimited, 1000), None);
+    }
+
+    #[test]
+    fn test_search_by_tag() {
+        let mut tickets = vec![
+            ticket(1, 0, None, Some(100), None),
+            ticket(2, 0, None, Some(300), None),
+            ticket(3, 0, None, None, None),
+            ticket(4, 0, None, Some(200), None),
+        ];
+        for ticket in &mut tickets[..3] {
+            ticket.tags.push("refund".to_string());
+        }
+        tickets[1].status = TicketStatus::Archived;
+
+        let ids: Vec<u32> = tagged(&tickets, " #Refund").iter().map(|ticket| ticket.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert!(tagged(&tickets, "duplicate").is_empty());
+    }
+}
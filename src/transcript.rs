@@ -0,0 +1,272 @@
+use serenity::prelude::*;
+use serenity::model::prelude::*;
+use serenity::builder::GetMessages;
+
+use crate::tickets::TicketNote;
+
+/// Channel history is fetched in pages of this size, the API maximum.
+const PAGE_SIZE: u8 = 100;
+
+pub struct TranscriptEmbed {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub fields: Vec<(String, String)>,
+}
+
+pub struct TranscriptMessage {
+    pub author: String,
+    pub author_id: u64,
+    pub timestamp: String,
+    pub content: String,
+    /// File name and URL of each attachment.
+    pub attachments: Vec<(String, String)>,
+    pub embeds: Vec<TranscriptEmbed>,
+}
+
+impl From<&Message> for TranscriptMessage {
+    fn from(message: &Message) -> Self {
+        TranscriptMessage {
+            author: message.author.name.clone(),
+            author_id: message.author.id.get(),
+            timestamp: message.timestamp.to_string(),
+            content: message.content.clone(),
+            attachments: message.attachments
+                .iter()
+                .map(|attachment| (attachment.filename.clone(), attachment.url.clone()))
+                .collect(),
+            embeds: message.embeds
+                .iter()
+                .map(|embed| TranscriptEmbed {
+                    title: embed.title.clone(),
+                    description: embed.description.clone(),
+                    url: embed.url.clone(),
+                    fields: embed.fields.iter().map(|field| (field.name.clone(), field.value.clone())).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A moderator note, rendered after the conversation in the staff copy.
+pub struct TranscriptNote {
+    pub author_id: u64,
+    pub timestamp: String,
+    pub text: String,
+}
+
+impl From<&TicketNote> for TranscriptNote {
+    fn from(note: &TicketNote) -> Self {
+        TranscriptNote {
+            author_id: note.author_id,
+            timestamp: Timestamp::from_unix_timestamp(note.created_at as i64)
+                .map(|timestamp| timestamp.to_string())
+                .unwrap_or_default(),
+            text: note.text.clone(),
+        }
+    }
+}
+
+/// A rendered conversation, oldest message first.
+pub struct Transcript {
+    pub title: String,
+    pub messages: Vec<TranscriptMessage>,
+    /// Empty in the copy sent to the opener.
+    pub notes: Vec<TranscriptNote>,
+}
+
+/// One copy of a transcript in both formats.
+pub struct RenderedTranscript {
+    pub html: String,
+    pub markdown: String,
+}
+
+/// Pages backwards through the whole channel history.
+pub async fn fetch_transcript(ctx: &Context, channel_id: ChannelId, title: String) -> Result<Transcript, serenity::Error> {
+    let mut messages = Vec::new();
+    let mut before: Option<MessageId> = None;
+    loop {
+        let mut request = GetMessages::new().limit(PAGE_SIZE);
+        if let Some(before) = before {
+            request = request.before(before);
+        }
+        let page = channel_id.messages(&ctx.http, request).await?;
+        let Some(oldest) = page.last() else {
+            break;
+        };
+        before = Some(oldest.id);
+        let page_len = page.len();
+        messages.extend(page.iter().map(TranscriptMessage::from));
+        if page_len < PAGE_SIZE as usize {
+            break;
+        }
+    }
+    messages.reverse();
+    Ok(Transcript { title, messages, notes: Vec::new() })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Transcript {
+    /// Renders the opener's copy, then the staff copy with `notes` added.
+    /// Private notes never reach the opener's copy.
+    pub fn render_copies(&mut self, notes: &[TicketNote]) -> (RenderedTranscript, RenderedTranscript) {
+        self.notes.clear();
+        let opener = RenderedTranscript { html: self.to_html(), markdown: self.to_markdown() };
+        self.notes = notes.iter().map(TranscriptNote::from).collect();
+        let staff = RenderedTranscript { html: self.to_html(), markdown: self.to_markdown() };
+        (opener, staff)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n\n", self.title);
+        for message in &self.messages {
+            out.push_str(&format!("**{}** (`{}`) — {}\n", message.author, message.author_id, message.timestamp));
+            if !message.content.is_empty() {
+                for line in message.content.lines() {
+                    out.push_str(&format!("> {}\n", line));
+                }
+            }
+            for (filename, url) in &message.attachments {
+                out.push_str(&format!("📎 [{}]({})\n", filename, url));
+            }
+            for embed in &message.embeds {
+                let title = embed.title.as_deref().unwrap_or("Embed");
+                match &embed.url {
+                    Some(url) => out.push_str(&format!("> **[{}]({})**\n", title, url)),
+                    None => out.push_str(&format!("> **{}**\n", title)),
+                }
+                if let Some(description) = &embed.description {
+                    for line in description.lines() {
+                        out.push_str(&format!("> {}\n", line));
+                    }
+                }
+                for (name, value) in &embed.fields {
+                    out.push_str(&format!("> **{}**: {}\n", name, value));
+                }
+            }
+            out.push('\n');
+        }
+        if !self.notes.is_empty() {
+            out.push_str("## Staff notes\n\n");
+            for note in &self.notes {
+                out.push_str(&format!("- `{}` — {}: {}\n", note.author_id, note.timestamp, note.text));
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\n<style>\
+             body{{font-family:sans-serif;background:#313338;color:#dbdee1;margin:2em}}\
+             .message{{margin:0 0 1em}}.author{{font-weight:bold;color:#fff}}\
+             .time{{color:#949ba4;font-size:.8em;margin-left:.5em}}.content{{white-space:pre-wrap}}\
+             .embed{{border-left:4px solid #5865f2;background:#2b2d31;padding:.5em 1em;margin-top:.3em}}\
+             a{{color:#00a8fc}}</style></head><body>\n<h1>{title}</h1>\n",
+            title = escape_html(&self.title)
+        );
+        for message in &self.messages {
+            out.push_str(&format!(
+                "<div class=\"message\"><span class=\"author\" title=\"{}\">{}</span><span class=\"time\">{}</span>\n",
+                message.author_id,
+                escape_html(&message.author),
+                escape_html(&message.timestamp)
+            ));
+            if !message.content.is_empty() {
+                out.push_str(&format!("<div class=\"content\">{}</div>\n", escape_html(&message.content)));
+            }
+            for (filename, url) in &message.attachments {
+                out.push_str(&format!(
+                    "<div>📎 <a href=\"{}\">{}</a></div>\n",
+                    escape_html(url),
+                    escape_html(filename)
+                ));
+            }
+            for embed in &message.embeds {
+                out.push_str("<div class=\"embed\">");
+                let title = escape_html(embed.title.as_deref().unwrap_or("Embed"));
+                match &embed.url {
+                    Some(url) => out.push_str(&format!("<b><a href=\"{}\">{}</a></b>", escape_html(url), title)),
+                    None => out.push_str(&format!("<b>{}</b>", title)),
+                }
+                if let Some(description) = &embed.description {
+                    out.push_str(&format!("<div class=\"content\">{}</div>", escape_html(description)));
+                }
+                for (name, value) in &embed.fields {
+                    out.push_str(&format!("<div><b>{}</b>: {}</div>", escape_html(name), escape_html(value)));
+                }
+                out.push_str("</div>\n");
+            }
+            out.push_str("</div>\n");
+        }
+        if !self.notes.is_empty() {
+            out.push_str("<h2>Staff notes</h2>\n");
+            for note in &self.notes {
+                out.push_str(&format!(
+                    "<div class=\"embed\"><span class=\"author\">{}</span><span class=\"time\">{}</span><div class=\"content\">{}</div></div>\n",
+                    note.author_id,
+                    escape_html(&note.timestamp),
+                    escape_html(&note.text)
+                ));
+            }
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript() -> Transcript {
+        Transcript {
+            title: "Ticket #10000".to_string(),
+            messages: vec![TranscriptMessage {
+                author: "opener".to_string(),
+                author_id: 1,
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                content: "line one\n<b>line two</b>".to_string(),
+                attachments: vec![("log.txt".to_string(), "https://cdn.example/log.txt".to_string())],
+                embeds: vec![TranscriptEmbed {
+                    title: Some("Intake".to_string()),
+                    description: None,
+                    url: None,
+                    fields: vec![("Order".to_string(), "42".to_string())],
+                }],
+            }],
+            notes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_rendering() {
+        let transcript = transcript();
+        let markdown = transcript.to_markdown();
+        assert!(markdown.starts_with("# Ticket #10000\n"));
+        assert!(markdown.contains("> line one\n> <b>line two</b>\n"));
+        assert!(markdown.contains("📎 [log.txt](https://cdn.example/log.txt)"));
+        assert!(markdown.contains("> **Order**: 42"));
+
+        let html = transcript.to_html();
+        assert!(html.contains("&lt;b&gt;line two&lt;/b&gt;"));
+        assert!(!html.contains("<b>line two</b>"));
+    }
+
+    #[test]
+    fn test_notes_only_in_staff_copy() {
+        let note = TicketNote { author_id: 7, created_at: 0, text: "private remark".to_string() };
+        let (opener, staff) = transcript().render_copies(&[note]);
+        assert!(!opener.markdown.contains("private remark"));
+        assert!(!opener.html.contains("private remark"));
+        assert!(staff.markdown.contains("## Staff notes"));
+        assert!(staff.markdown.contains("private remark"));
+        assert!(staff.html.contains("private remark"));
+    }
+}